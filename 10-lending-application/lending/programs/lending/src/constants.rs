@@ -1,10 +1,35 @@
 use anchor_lang::prelude::*;
 
 #[constant]
-pub const SOL_USD_FEED_ID: &str =
-    "0xef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
+pub const WAD: u128 = 1_000_000_000_000_000_000;
 #[constant]
-pub const USDC_USD_FEED_ID: &str =
-    "0xeaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a";
+pub const SECONDS_PER_YEAR: u64 = 31_536_000;
 #[constant]
-pub const MAX_AGE: u64 = 100;
+pub const BPS: u64 = 10_000;
+#[constant]
+pub const MAX_LIQUIDATION_BONUS: u64 = 2_000;
+// Annual, in basis points.
+#[constant]
+pub const MAX_LATE_PENALTY_RATE: u64 = 10_000;
+// Annual, in basis points. Bounds the borrow rate at full utilization.
+#[constant]
+pub const MAX_BORROW_RATE: u64 = 30_000;
+
+pub const MAX_POSITIONS: usize = 8;
+
+// Bank::paused flags. A set bit halts the operation.
+#[constant]
+pub const PAUSE_DEPOSIT: u8 = 1 << 0;
+#[constant]
+pub const PAUSE_WITHDRAW: u8 = 1 << 1;
+#[constant]
+pub const PAUSE_BORROW: u8 = 1 << 2;
+#[constant]
+pub const PAUSE_REPAY: u8 = 1 << 3;
+#[constant]
+pub const PAUSE_LIQUIDATE: u8 = 1 << 4;
+#[constant]
+pub const PAUSE_ALL: u8 = PAUSE_DEPOSIT | PAUSE_WITHDRAW | PAUSE_BORROW | PAUSE_REPAY | PAUSE_LIQUIDATE;
+
+// Transfer hook programs a listed Token-2022 mint may point at.
+pub const TRANSFER_HOOK_ALLOWLIST: &[Pubkey] = &[];
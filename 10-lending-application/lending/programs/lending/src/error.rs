@@ -13,4 +13,157 @@ pub enum ErrorCode {
 
     #[msg("Not under collateralized, cannot be liquidated")]
     NotUnderCollateralized,
+
+    #[msg("Liquidation amount is too small")]
+    LiquidationTooSmall,
+
+    #[msg("Collateral and borrowed banks must differ")]
+    InvalidLiquidationBanks,
+
+    #[msg("Borrower still has collateral and must be liquidated first")]
+    BorrowerHasCollateral,
+
+    #[msg("Borrower has no debt in this bank")]
+    NoBadDebt,
+
+    #[msg("Amount exceeds protocol reserves")]
+    InsufficientReserves,
+
+    #[msg("Bank still has deposits, borrows or reserves")]
+    BankNotEmpty,
+
+    #[msg("A flash loan is already outstanding for this bank")]
+    FlashLoanInProgress,
+
+    #[msg("No flash loan is outstanding for this bank")]
+    NoFlashLoan,
+
+    #[msg("Flash borrow must be followed by a matching flash repay")]
+    MissingFlashRepay,
+
+    #[msg("Flash loan instructions cannot be invoked via CPI")]
+    FlashLoanCpi,
+
+    #[msg("Invalid flash loan fee")]
+    InvalidFlashLoanFee,
+
+    #[msg("Math operation overflowed")]
+    MathOverflow,
+
+    #[msg("Amount is too small to be represented in shares")]
+    ZeroShares,
+
+    #[msg("User has no free position slots")]
+    PositionLimitReached,
+
+    #[msg("User has no position in this bank")]
+    PositionNotFound,
+
+    #[msg("User still has open positions")]
+    OpenPositions,
+
+    #[msg("Bank and price accounts for every position must be provided")]
+    MissingPositionAccounts,
+
+    #[msg("Operation would leave the position undercollateralized")]
+    UnhealthyPosition,
+
+    #[msg("Invalid interest rate model parameters")]
+    InvalidInterestRateModel,
+
+    #[msg("Invalid risk configuration")]
+    InvalidRiskConfig,
+
+    #[msg("Invalid isolation configuration")]
+    InvalidIsolationConfig,
+
+    #[msg("Invalid e-mode configuration")]
+    InvalidEModeConfig,
+
+    #[msg("Invalid fixed-term configuration")]
+    InvalidFixedTermConfig,
+
+    #[msg("Fixed-term borrowing is disabled for this bank")]
+    FixedTermDisabled,
+
+    #[msg("Term must be positive and within the bank's maximum term")]
+    InvalidFixedTerm,
+
+    #[msg("User already has a fixed-term borrow in this bank")]
+    FixedBorrowExists,
+
+    #[msg("User has no fixed-term borrow in this bank")]
+    NoFixedBorrow,
+
+    #[msg("Signer is neither the owner nor an approved borrow delegate")]
+    InvalidBorrowDelegate,
+
+    #[msg("Borrow exceeds the delegate's remaining allowance")]
+    BorrowAllowanceExceeded,
+
+    #[msg("Isolated collateral cannot back debt in this bank")]
+    NotBorrowableInIsolation,
+
+    #[msg("Borrow would exceed the isolated collateral debt ceiling")]
+    DebtCeilingExceeded,
+
+    #[msg("Invalid oracle configuration")]
+    InvalidOracleConfig,
+
+    #[msg("Oracle price confidence interval is too wide")]
+    PriceConfidenceTooWide,
+
+    #[msg("Oracle price must be positive")]
+    InvalidPrice,
+
+    #[msg("Oracle price is stale")]
+    StalePrice,
+
+    #[msg("Fallback oracle account does not match the bank configuration")]
+    InvalidFallbackOracle,
+
+    #[msg("Primary and fallback oracle prices deviate too much")]
+    PriceDeviationTooHigh,
+
+    #[msg("Deposit would exceed the bank deposit cap")]
+    DepositCapExceeded,
+
+    #[msg("Borrow would exceed the bank borrow cap")]
+    BorrowCapExceeded,
+
+    #[msg("Mint has an unsupported token extension")]
+    UnsupportedMintExtension,
+
+    #[msg("Operation is paused for this bank")]
+    MarketPaused,
+
+    #[msg("Unknown pause flags")]
+    InvalidPauseFlags,
+
+    #[msg("Signer is neither the pause guardian nor the bank authority")]
+    InvalidPauseAuthority,
+
+    #[msg("Native SOL moves as lamports and pays out through the unwrap account; other mints need the user's token account")]
+    InvalidUnwrapAccount,
+
+    #[msg("Flash loan repayment arrived short of the amount owed")]
+    FlashLoanNotRepaid,
+
+    #[msg("Receipt mint still has supply from a previous listing")]
+    ReceiptMintInUse,
+
+    #[msg("Isolated bank account does not match the obligation's isolated collateral")]
+    InvalidIsolatedBank,
+
+    #[msg("Obligation holds collateral in more than one isolated bank")]
+    MultipleIsolatedCollateral,
+
+    #[msg("Fixed-term loans are not available for mints with a transfer fee")]
+    TransferFeeNotSupported,
+
+    #[msg("Only the owner can open a fixed-term loan")]
+    FixedBorrowOwnerOnly,
+
+    #[msg("Pause guardian must be a key other than the bank authority")]
+    InvalidPauseGuardian,
 }
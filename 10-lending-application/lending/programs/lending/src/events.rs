@@ -0,0 +1,286 @@
+use anchor_lang::prelude::*;
+
+use crate::state::{
+    EModeConfig, FixedTermConfig, InterestRateModel, IsolationConfig, OracleConfig, RiskConfig,
+};
+
+// Health factors are liquidation value over debt value, scaled by WAD. Positions
+// without debt report u128::MAX.
+
+#[event]
+pub struct BankInitialized {
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub receipt_mint: Pubkey,
+    pub authority: Pubkey,
+    pub risk_config: RiskConfig,
+    pub interest_rate_model: InterestRateModel,
+    pub oracle_config: OracleConfig,
+}
+
+#[event]
+pub struct BankClosed {
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub authority: Pubkey,
+    pub swept_treasury: u64,
+    pub swept_insurance: u64,
+}
+
+#[event]
+pub struct UserClosed {
+    pub user: Pubkey,
+}
+
+#[event]
+pub struct Deposited {
+    pub user: Pubkey,
+    pub bank: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub supply_index: u128,
+    // None when the remaining accounts do not price every position.
+    pub health_factor: Option<u128>,
+}
+
+#[event]
+pub struct Withdrawn {
+    pub user: Pubkey,
+    pub bank: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub supply_index: u128,
+    pub health_factor: u128,
+}
+
+#[event]
+pub struct CollateralDeposited {
+    pub user: Pubkey,
+    pub bank: Pubkey,
+    pub shares: u64,
+}
+
+#[event]
+pub struct CollateralWithdrawn {
+    pub user: Pubkey,
+    pub bank: Pubkey,
+    pub shares: u64,
+    pub health_factor: u128,
+}
+
+#[event]
+pub struct BorrowDelegateApproved {
+    pub owner: Pubkey,
+    pub bank: Pubkey,
+    pub delegate: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct BorrowDelegateRevoked {
+    pub owner: Pubkey,
+    pub bank: Pubkey,
+    pub delegate: Pubkey,
+}
+
+#[event]
+pub struct Borrowed {
+    pub user: Pubkey,
+    pub signer: Pubkey,
+    pub bank: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub borrow_index: u128,
+    pub health_factor: u128,
+}
+
+#[event]
+pub struct FixedBorrowed {
+    pub user: Pubkey,
+    pub signer: Pubkey,
+    pub bank: Pubkey,
+    pub amount: u64,
+    pub rate: u64,
+    pub maturity: i64,
+    pub health_factor: u128,
+}
+
+#[event]
+pub struct Repaid {
+    pub user: Pubkey,
+    pub bank: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub borrow_index: u128,
+    pub health_factor: Option<u128>,
+}
+
+#[event]
+pub struct FixedRepaid {
+    pub user: Pubkey,
+    pub bank: Pubkey,
+    pub amount: u64,
+    pub principal: u64,
+    pub early_repay_penalty: u64,
+    pub health_factor: Option<u128>,
+}
+
+#[event]
+pub struct FlashBorrowed {
+    pub signer: Pubkey,
+    pub bank: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct FlashRepaid {
+    pub signer: Pubkey,
+    pub bank: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct Liquidated {
+    pub liquidator: Pubkey,
+    pub borrower: Pubkey,
+    pub collateral_bank: Pubkey,
+    pub borrowed_bank: Pubkey,
+    pub repay_amount: u64,
+    pub repaid_shares: u64,
+    pub fixed_repay_amount: u64,
+    pub seized_amount: u64,
+    pub seized_shares: u64,
+    pub health_factor: u128,
+}
+
+#[event]
+pub struct BadDebtSettled {
+    pub borrower: Pubkey,
+    pub bank: Pubkey,
+    pub debt: u64,
+    pub shares: u64,
+    pub covered: u64,
+    pub socialized: u64,
+    pub supply_index: u128,
+}
+
+#[event]
+pub struct InterestAccrued {
+    pub bank: Pubkey,
+    pub supply_index: u128,
+    pub borrow_index: u128,
+    pub total_deposits: u64,
+    pub total_borrowed: u64,
+    pub protocol_reserves: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProtocolFeesCollected {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub fee_receiver: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct InsuranceFunded {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct FallbackPriceUpdated {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub price: i64,
+    pub exponent: i32,
+    pub conf: u64,
+    pub publish_time: i64,
+}
+
+#[event]
+pub struct BankConfigUpdated {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub old_config: RiskConfig,
+    pub new_config: RiskConfig,
+}
+
+#[event]
+pub struct OracleConfigUpdated {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub old_config: OracleConfig,
+    pub new_config: OracleConfig,
+}
+
+#[event]
+pub struct IsolationConfigUpdated {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub old_config: IsolationConfig,
+    pub new_config: IsolationConfig,
+}
+
+#[event]
+pub struct EModeConfigUpdated {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub old_config: EModeConfig,
+    pub new_config: EModeConfig,
+}
+
+#[event]
+pub struct FixedTermConfigUpdated {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub old_config: FixedTermConfig,
+    pub new_config: FixedTermConfig,
+}
+
+#[event]
+pub struct InterestRateModelUpdated {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub old_model: InterestRateModel,
+    pub new_model: InterestRateModel,
+}
+
+#[event]
+pub struct CapsUpdated {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub deposit_cap: u64,
+    pub borrow_cap: u64,
+}
+
+#[event]
+pub struct FlashLoanFeeUpdated {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub flash_loan_fee: u64,
+}
+
+#[event]
+pub struct PauseGuardianUpdated {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub pause_guardian: Pubkey,
+}
+
+#[event]
+pub struct PauseUpdated {
+    pub bank: Pubkey,
+    pub signer: Pubkey,
+    pub paused: u8,
+}
+
+#[event]
+pub struct FeeReceiverUpdated {
+    pub bank: Pubkey,
+    pub authority: Pubkey,
+    pub fee_receiver: Pubkey,
+}
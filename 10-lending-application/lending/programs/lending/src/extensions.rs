@@ -0,0 +1,56 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_hook, BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+};
+
+use crate::{constants::TRANSFER_HOOK_ALLOWLIST, error::ErrorCode};
+
+// Transfer fees are handled by crediting what the treasury actually receives. Extensions
+// that let tokens move without the bank's consent, or that the bank cannot move at all,
+// are rejected at listing time.
+pub fn validate_mint_extensions(mint: &AccountInfo) -> Result<()> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+
+    for extension in state.get_extension_types()? {
+        match extension {
+            ExtensionType::PermanentDelegate | ExtensionType::NonTransferable => {
+                return Err(ErrorCode::UnsupportedMintExtension.into());
+            }
+            ExtensionType::TransferHook => {
+                if let Some(program_id) = transfer_hook::get_program_id(&state) {
+                    if !TRANSFER_HOOK_ALLOWLIST.contains(&program_id) {
+                        return Err(ErrorCode::UnsupportedMintExtension.into());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Fixed-term loans are repaid in one exact amount, which a transfer fee would always leave short.
+pub fn check_no_transfer_fee(mint: &AccountInfo) -> Result<()> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+
+    if state
+        .get_extension_types()?
+        .contains(&ExtensionType::TransferFeeConfig)
+    {
+        return Err(ErrorCode::TransferFeeNotSupported.into());
+    }
+
+    Ok(())
+}
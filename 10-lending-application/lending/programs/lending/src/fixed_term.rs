@@ -0,0 +1,111 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    constants::{BPS, SECONDS_PER_YEAR, WAD},
+    error::ErrorCode,
+    interest::{borrow_rate, utilization},
+    math::{mul_div, to_u64, Rounding},
+    state::{Bank, FixedBorrow},
+};
+
+// Fixed-term loans accrue simple interest on principal at the locked rate until maturity
+// and at the locked late penalty rate afterwards. They sit outside the borrow index, so
+// their interest only reaches suppliers when it is paid.
+
+// The variable borrow rate at the current utilization plus the bank's premium. Called after
+// the new principal is recorded so the loan prices its own impact on the pool.
+pub fn fixed_rate(bank: &Bank) -> Result<u64> {
+    let variable_rate = borrow_rate(&bank.interest_rate_model, utilization(bank)?)?;
+    let variable_rate = to_u64(mul_div(variable_rate, BPS as u128, WAD, Rounding::Up)?)?;
+
+    variable_rate
+        .checked_add(bank.fixed_term.rate_premium)
+        .ok_or(ErrorCode::MathOverflow.into())
+}
+
+fn simple_interest(principal: u64, rate: u64, from: i64, to: i64) -> Result<u64> {
+    if to <= from {
+        return Ok(0);
+    }
+
+    to_u64(mul_div(
+        principal as u128 * rate as u128,
+        (to - from) as u128,
+        BPS as u128 * SECONDS_PER_YEAR as u128,
+        Rounding::Up,
+    )?)
+}
+
+// Unpaid interest and late penalties as of `now`.
+pub fn accrued_interest(fixed_borrow: &FixedBorrow, now: i64) -> Result<u64> {
+    let interest = simple_interest(
+        fixed_borrow.principal,
+        fixed_borrow.rate,
+        fixed_borrow.last_accrued,
+        now.min(fixed_borrow.maturity),
+    )?;
+    let late_penalty = simple_interest(
+        fixed_borrow.principal,
+        fixed_borrow.late_penalty_rate,
+        fixed_borrow.last_accrued.max(fixed_borrow.maturity),
+        now,
+    )?;
+
+    fixed_borrow
+        .accrued
+        .checked_add(interest)
+        .and_then(|accrued| accrued.checked_add(late_penalty))
+        .ok_or(ErrorCode::MathOverflow.into())
+}
+
+// Principal plus accrued interest and late penalties, excluding any early-repay penalty.
+pub fn fixed_debt(fixed_borrow: &FixedBorrow, now: i64) -> Result<u64> {
+    fixed_borrow
+        .principal
+        .checked_add(accrued_interest(fixed_borrow, now)?)
+        .ok_or(ErrorCode::MathOverflow.into())
+}
+
+pub fn early_repay_penalty(fixed_borrow: &FixedBorrow, now: i64) -> Result<u64> {
+    if now >= fixed_borrow.maturity {
+        return Ok(0);
+    }
+
+    to_u64(mul_div(
+        fixed_borrow.principal as u128,
+        fixed_borrow.early_repay_penalty as u128,
+        BPS as u128,
+        Rounding::Up,
+    )?)
+}
+
+// Pays interest and penalties first, then principal. Paid interest is split between suppliers
+// and reserves like any other fee. Returns the principal repaid.
+pub fn apply_fixed_repayment(
+    bank: &mut Bank,
+    fixed_borrow: &mut FixedBorrow,
+    amount: u64,
+    now: i64,
+) -> Result<u64> {
+    fixed_borrow.accrued = accrued_interest(fixed_borrow, now)?;
+    fixed_borrow.last_accrued = now;
+
+    let interest_paid = amount.min(fixed_borrow.accrued);
+    let principal_paid = amount - interest_paid;
+
+    if principal_paid > fixed_borrow.principal {
+        return Err(ErrorCode::OverRepay.into());
+    }
+
+    fixed_borrow.accrued -= interest_paid;
+    fixed_borrow.principal -= principal_paid;
+
+    if fixed_borrow.principal == 0 {
+        *fixed_borrow = FixedBorrow::default();
+    }
+
+    bank.total_fixed_borrowed -= principal_paid;
+    bank.credit_fee(interest_paid)?;
+
+    Ok(principal_paid)
+}
@@ -0,0 +1,249 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::{
+    constants::{BPS, WAD},
+    error::ErrorCode,
+    fixed_term::fixed_debt,
+    interest::accrue_interest,
+    math::{mul_div, Rounding},
+    price::{read_price, Price},
+    state::{Bank, FallbackPrice, User},
+};
+
+pub struct PricedBank {
+    pub key: Pubkey,
+    pub bank: Bank,
+    pub price: Price,
+}
+
+pub struct Health {
+    pub borrowable_value: u128,
+    pub liquidation_value: u128,
+    pub debt_value: u128,
+
+    // Non-zero when every position shares the same e-mode category.
+    pub emode_category: u8,
+    // Isolated banks the obligation holds collateral in, and the least room left under
+    // their debt ceilings.
+    pub isolated_banks: Vec<Pubkey>,
+    pub debt_ceiling_headroom: Option<u128>,
+    // Set once the obligation owes a bank that is not borrowable in isolation, at which point
+    // isolated collateral adds nothing to `borrowable_value`.
+    pub has_debt_outside_isolation: bool,
+}
+
+impl Health {
+    pub fn can_borrow(&self) -> bool {
+        self.debt_value <= self.borrowable_value
+    }
+
+    pub fn is_liquidatable(&self) -> bool {
+        self.debt_value > self.liquidation_value
+    }
+
+    // Liquidation value over debt value, scaled by WAD; u128::MAX without debt. Both values are
+    // scaled down together until the product fits, which only drops digits far below a cent.
+    pub fn health_factor(&self) -> Result<u128> {
+        let scale = self.liquidation_value.max(self.debt_value) / (u128::MAX / WAD) + 1;
+        let debt_value = self.debt_value / scale;
+
+        if debt_value == 0 {
+            return Ok(u128::MAX);
+        }
+
+        mul_div(self.liquidation_value / scale, WAD, debt_value, Rounding::Down)
+    }
+}
+
+fn load_account<T: AccountDeserialize + Owner>(info: &AccountInfo) -> Result<T> {
+    if *info.owner != T::owner() {
+        return Err(anchor_lang::error::ErrorCode::AccountOwnedByWrongProgram.into());
+    }
+
+    T::try_deserialize(&mut &info.try_borrow_data()?[..])
+}
+
+// Remaining accounts are expected as [bank, price_update] per position, followed by the
+// bank's fallback price account when one is configured.
+pub fn load_priced_banks(remaining_accounts: &[AccountInfo], clock: &Clock) -> Result<Vec<PricedBank>> {
+    let mut accounts = remaining_accounts.iter();
+    let mut priced_banks = Vec::new();
+
+    while let Some(bank_info) = accounts.next() {
+        let mut bank = load_account::<Bank>(bank_info)?;
+        let price_update = load_account::<PriceUpdateV2>(
+            accounts.next().ok_or(ErrorCode::MissingPositionAccounts)?,
+        )?;
+
+        let fallback = if bank.oracle.has_fallback() {
+            let fallback_info = accounts.next().ok_or(ErrorCode::MissingPositionAccounts)?;
+
+            if fallback_info.key() != bank.oracle.fallback_oracle {
+                return Err(ErrorCode::InvalidFallbackOracle.into());
+            }
+
+            Some(load_account::<FallbackPrice>(fallback_info)?)
+        } else {
+            None
+        };
+
+        accrue_interest(&mut bank, clock.unix_timestamp)?;
+        let price = read_price(&price_update, fallback.as_ref(), &bank, &bank_info.key(), clock)?;
+
+        priced_banks.push(PricedBank {
+            key: bank_info.key(),
+            bank,
+            price,
+        });
+    }
+
+    Ok(priced_banks)
+}
+
+pub fn upsert_priced_bank(priced_banks: &mut Vec<PricedBank>, key: Pubkey, bank: &Bank, price: Price) {
+    let priced_bank = PricedBank {
+        key,
+        bank: bank.clone(),
+        price,
+    };
+
+    match priced_banks.iter_mut().find(|existing| existing.key == key) {
+        Some(existing) => *existing = priced_bank,
+        None => priced_banks.push(priced_bank),
+    }
+}
+
+// Health factor after an instruction that only moves `bank`'s side of the obligation and has
+// no price of its own: positions are priced from the remaining accounts, with `bank` replaced
+// by its updated state. Obligations without debt need no prices. Only ever reported, never
+// enforced, so missing or stale prices yield None rather than failing the instruction.
+pub fn health_factor_after(
+    user: &User,
+    bank_key: Pubkey,
+    bank: &Bank,
+    remaining_accounts: &[AccountInfo],
+    clock: &Clock,
+) -> Option<u128> {
+    if !user.positions.iter().any(|position| position.has_debt()) {
+        return Some(u128::MAX);
+    }
+
+    let mut priced_banks = load_priced_banks(remaining_accounts, clock).ok()?;
+
+    if let Some(price) = priced_banks
+        .iter()
+        .find(|priced_bank| priced_bank.key == bank_key)
+        .map(|priced_bank| priced_bank.price)
+    {
+        upsert_priced_bank(&mut priced_banks, bank_key, bank, price);
+    }
+
+    compute_health(user, &priced_banks).ok()?.health_factor().ok()
+}
+
+fn find_priced_bank<'a>(priced_banks: &'a [PricedBank], key: &Pubkey) -> Result<&'a PricedBank> {
+    priced_banks
+        .iter()
+        .find(|priced_bank| priced_bank.key == *key)
+        .ok_or(ErrorCode::MissingPositionAccounts.into())
+}
+
+pub fn compute_health(user: &User, priced_banks: &[PricedBank]) -> Result<Health> {
+    compute_health_opening(user, priced_banks, None)
+}
+
+// Like `compute_health`, but treats `opening` as a position the user is about to open when
+// deciding whether the obligation qualifies for e-mode.
+pub fn compute_health_opening(
+    user: &User,
+    priced_banks: &[PricedBank],
+    opening: Option<&Pubkey>,
+) -> Result<Health> {
+    let mut health = Health {
+        borrowable_value: 0,
+        liquidation_value: 0,
+        debt_value: 0,
+        emode_category: 0,
+        isolated_banks: Vec::new(),
+        debt_ceiling_headroom: None,
+        has_debt_outside_isolation: false,
+    };
+
+    let mut open_banks = Vec::new();
+
+    for position in user.positions.iter().filter(|position| !position.is_empty()) {
+        open_banks.push((position, find_priced_bank(priced_banks, &position.bank)?));
+    }
+
+    let mut categories = open_banks
+        .iter()
+        .map(|(_, priced_bank)| priced_bank.bank.emode.category)
+        .collect::<Vec<_>>();
+
+    if let Some(opening) = opening {
+        categories.push(find_priced_bank(priced_banks, opening)?.bank.emode.category);
+    }
+
+    let first_category = categories.first().copied().unwrap_or(0);
+
+    if categories.iter().all(|category| *category == first_category) {
+        health.emode_category = first_category;
+    }
+
+    // Isolated collateral stops counting towards borrowing power as soon as the obligation
+    // owes anything to a bank that is not borrowable in isolation.
+    health.has_debt_outside_isolation = open_banks.iter().any(|(position, priced_bank)| {
+        position.has_debt() && !priced_bank.bank.isolation.borrowable_in_isolation
+    });
+
+    for (position, priced_bank) in open_banks {
+        let bank = &priced_bank.bank;
+        let price = &priced_bank.price;
+
+        let deposited = bank.deposit_shares_to_amount(position.deposited_shares)?;
+        // Priced banks are accrued to the current timestamp, so `last_updated` is now.
+        let borrowed = bank
+            .borrow_shares_to_amount(position.borrowed_shares)?
+            .checked_add(fixed_debt(&position.fixed_borrow, bank.last_updated)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        let deposit_value = price.value_of(deposited, bank.mint_decimals)?;
+        let debt_value = price.value_of(borrowed, bank.mint_decimals)?;
+
+        let (max_ltv, liquidation_threshold) = bank.collateral_factors(health.emode_category);
+
+        if bank.isolation.isolated && position.deposited_shares > 0 {
+            let headroom = bank.isolation.debt_ceiling.saturating_sub(bank.isolated_debt);
+
+            health.isolated_banks.push(priced_bank.key);
+            health.debt_ceiling_headroom = Some(
+                health
+                    .debt_ceiling_headroom
+                    .map_or(headroom, |debt_ceiling_headroom| debt_ceiling_headroom.min(headroom)),
+            );
+        }
+
+        if !(bank.isolation.isolated && health.has_debt_outside_isolation) {
+            health.borrowable_value = health
+                .borrowable_value
+                .checked_add(mul_div(deposit_value, max_ltv as u128, BPS as u128, Rounding::Down)?)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        health.liquidation_value = health
+            .liquidation_value
+            .checked_add(mul_div(
+                deposit_value,
+                liquidation_threshold as u128,
+                BPS as u128,
+                Rounding::Down,
+            )?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        health.debt_value = health
+            .debt_value
+            .checked_add(debt_value)
+            .ok_or(ErrorCode::MathOverflow)?;
+    }
+
+    Ok(health)
+}
@@ -1,27 +1,296 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
-use crate::state::{Bank, User};
+use crate::constants::{BPS, PAUSE_ALL, WAD};
+use crate::error::ErrorCode;
+use crate::events::{
+    BankConfigUpdated, BankInitialized, CapsUpdated, EModeConfigUpdated, FeeReceiverUpdated,
+    FixedTermConfigUpdated, FlashLoanFeeUpdated, InterestRateModelUpdated, IsolationConfigUpdated,
+    OracleConfigUpdated, PauseGuardianUpdated, PauseUpdated,
+};
+use crate::extensions::validate_mint_extensions;
+use crate::interest::accrue_bank_interest;
+use crate::state::{
+    Bank, EModeConfig, FixedTermConfig, InterestRateModel, IsolationConfig, OracleConfig,
+    RiskConfig, User,
+};
+
+pub fn process_init_bank(
+    ctx: Context<InitBank>,
+    risk_config: RiskConfig,
+    interest_rate_model: InterestRateModel,
+    oracle_config: OracleConfig,
+) -> Result<()> {
+    risk_config.validate()?;
+    interest_rate_model.validate()?;
+    oracle_config.validate(&ctx.accounts.bank.key())?;
+    validate_mint_extensions(&ctx.accounts.mint.to_account_info())?;
 
-pub fn process_init_bank(ctx: Context<InitBank>, liquidation_threshold: u64, max_ltv: u64) -> Result<()> {
     let bank = &mut ctx.accounts.bank;
 
     bank.mint_address = ctx.accounts.mint.key();
+    bank.mint_decimals = ctx.accounts.mint.decimals;
+    bank.receipt_mint = ctx.accounts.receipt_mint.key();
     bank.authority = ctx.accounts.signer.key();
-    
-    bank.liquidation_threshold = liquidation_threshold;
-    bank.max_ltv = max_ltv;
+    bank.fee_receiver = ctx.accounts.signer.key();
+    // The authority can always pause; a separate guardian is only set once it appoints one.
+    bank.pause_guardian = Pubkey::default();
+
+    bank.set_risk_config(risk_config);
+
+    bank.interest_rate_model = interest_rate_model;
+    bank.oracle = oracle_config;
+
+    bank.supply_index = WAD;
+    bank.borrow_index = WAD;
+    bank.last_updated = Clock::get()?.unix_timestamp;
+
+    emit!(BankInitialized {
+        bank: bank.key(),
+        mint: bank.mint_address,
+        receipt_mint: bank.receipt_mint,
+        authority: bank.authority,
+        risk_config,
+        interest_rate_model,
+        oracle_config,
+    });
+
+    Ok(())
+}
+
+pub fn process_update_bank_config(ctx: Context<UpdateBankConfig>, risk_config: RiskConfig) -> Result<()> {
+    risk_config.validate()?;
+
+    let bank = &mut ctx.accounts.bank;
+
+    // The e-mode factors must stay at or above the new base factors.
+    bank.emode.validate(&risk_config)?;
+
+    let old_config = bank.risk_config();
+
+    bank.set_risk_config(risk_config);
+
+    emit!(BankConfigUpdated {
+        bank: bank.key(),
+        authority: ctx.accounts.authority.key(),
+        old_config,
+        new_config: risk_config,
+    });
+
+    Ok(())
+}
+
+pub fn process_update_oracle_config(
+    ctx: Context<UpdateOracleConfig>,
+    oracle_config: OracleConfig,
+) -> Result<()> {
+    oracle_config.validate(&ctx.accounts.bank.key())?;
+
+    let bank = &mut ctx.accounts.bank;
+    let old_config = bank.oracle;
+
+    bank.oracle = oracle_config;
+
+    emit!(OracleConfigUpdated {
+        bank: bank.key(),
+        authority: ctx.accounts.authority.key(),
+        old_config,
+        new_config: oracle_config,
+    });
+
+    Ok(())
+}
+
+pub fn process_update_isolation_config(
+    ctx: Context<UpdateIsolationConfig>,
+    isolation_config: IsolationConfig,
+) -> Result<()> {
+    isolation_config.validate()?;
+
+    let bank = &mut ctx.accounts.bank;
+    let old_config = bank.isolation;
 
-    bank.interest_rate = 5;
+    bank.isolation = isolation_config;
+
+    emit!(IsolationConfigUpdated {
+        bank: bank.key(),
+        authority: ctx.accounts.authority.key(),
+        old_config,
+        new_config: isolation_config,
+    });
 
     Ok(())
 }
 
-pub fn process_init_user(ctx: Context<InitUser>, usdc_address: Pubkey) -> Result<()> {
+pub fn process_update_emode_config(ctx: Context<UpdateEModeConfig>, emode_config: EModeConfig) -> Result<()> {
+    let bank = &mut ctx.accounts.bank;
+
+    emode_config.validate(&bank.risk_config())?;
+
+    let old_config = bank.emode;
+
+    bank.emode = emode_config;
+
+    emit!(EModeConfigUpdated {
+        bank: bank.key(),
+        authority: ctx.accounts.authority.key(),
+        old_config,
+        new_config: emode_config,
+    });
+
+    Ok(())
+}
+
+// Existing fixed-term loans keep the rate, maturity and penalty terms locked when they were
+// opened; new terms only apply to loans opened afterwards.
+pub fn process_update_fixed_term_config(
+    ctx: Context<UpdateFixedTermConfig>,
+    fixed_term_config: FixedTermConfig,
+) -> Result<()> {
+    fixed_term_config.validate()?;
+
+    let bank = &mut ctx.accounts.bank;
+    let old_config = bank.fixed_term;
+
+    bank.fixed_term = fixed_term_config;
+
+    emit!(FixedTermConfigUpdated {
+        bank: bank.key(),
+        authority: ctx.accounts.authority.key(),
+        old_config,
+        new_config: fixed_term_config,
+    });
+
+    Ok(())
+}
+
+pub fn process_update_interest_rate_model(
+    ctx: Context<UpdateInterestRateModel>,
+    interest_rate_model: InterestRateModel,
+) -> Result<()> {
+    interest_rate_model.validate()?;
+
+    let bank = &mut ctx.accounts.bank;
+
+    accrue_bank_interest(bank, Clock::get()?.unix_timestamp)?;
+
+    let old_model = bank.interest_rate_model;
+
+    bank.interest_rate_model = interest_rate_model;
+
+    emit!(InterestRateModelUpdated {
+        bank: bank.key(),
+        authority: ctx.accounts.authority.key(),
+        old_model,
+        new_model: interest_rate_model,
+    });
+
+    Ok(())
+}
+
+pub fn process_update_fee_receiver(ctx: Context<UpdateFeeReceiver>, fee_receiver: Pubkey) -> Result<()> {
+    ctx.accounts.bank.fee_receiver = fee_receiver;
+
+    emit!(FeeReceiverUpdated {
+        bank: ctx.accounts.bank.key(),
+        authority: ctx.accounts.authority.key(),
+        fee_receiver,
+    });
+
+    Ok(())
+}
+
+pub fn process_update_caps(ctx: Context<UpdateCaps>, deposit_cap: u64, borrow_cap: u64) -> Result<()> {
+    let bank = &mut ctx.accounts.bank;
+
+    bank.deposit_cap = deposit_cap;
+    bank.borrow_cap = borrow_cap;
+
+    emit!(CapsUpdated {
+        bank: bank.key(),
+        authority: ctx.accounts.authority.key(),
+        deposit_cap,
+        borrow_cap,
+    });
+
+    Ok(())
+}
+
+pub fn process_update_flash_loan_fee(ctx: Context<UpdateFlashLoanFee>, flash_loan_fee: u64) -> Result<()> {
+    if flash_loan_fee > BPS {
+        return Err(ErrorCode::InvalidFlashLoanFee.into());
+    }
+
+    ctx.accounts.bank.flash_loan_fee = flash_loan_fee;
+
+    emit!(FlashLoanFeeUpdated {
+        bank: ctx.accounts.bank.key(),
+        authority: ctx.accounts.authority.key(),
+        flash_loan_fee,
+    });
+
+    Ok(())
+}
+
+pub fn process_update_pause_guardian(
+    ctx: Context<UpdatePauseGuardian>,
+    pause_guardian: Pubkey,
+) -> Result<()> {
+    require!(
+        pause_guardian != ctx.accounts.authority.key(),
+        ErrorCode::InvalidPauseGuardian
+    );
+
+    ctx.accounts.bank.pause_guardian = pause_guardian;
+
+    emit!(PauseGuardianUpdated {
+        bank: ctx.accounts.bank.key(),
+        authority: ctx.accounts.authority.key(),
+        pause_guardian,
+    });
+
+    Ok(())
+}
+
+// The authority can set any combination of flags, including re-enabling operations.
+pub fn process_set_paused(ctx: Context<SetPaused>, paused: u8) -> Result<()> {
+    if paused & !PAUSE_ALL != 0 {
+        return Err(ErrorCode::InvalidPauseFlags.into());
+    }
+
+    ctx.accounts.bank.paused = paused;
+
+    emit!(PauseUpdated {
+        bank: ctx.accounts.bank.key(),
+        signer: ctx.accounts.authority.key(),
+        paused,
+    });
+
+    Ok(())
+}
+
+// The guardian can only add flags, never clear them.
+pub fn process_pause(ctx: Context<Pause>, operations: u8) -> Result<()> {
+    if operations & !PAUSE_ALL != 0 {
+        return Err(ErrorCode::InvalidPauseFlags.into());
+    }
+
+    let bank = &mut ctx.accounts.bank;
+
+    bank.paused |= operations;
+
+    emit!(PauseUpdated {
+        bank: bank.key(),
+        signer: ctx.accounts.signer.key(),
+        paused: bank.paused,
+    });
+
+    Ok(())
+}
+
+pub fn process_init_user(ctx: Context<InitUser>) -> Result<()> {
     let user_account = &mut ctx.accounts.user_account;
 
     user_account.owner = ctx.accounts.signer.key();
-    
-    user_account.usdc_address = usdc_address;
 
     Ok(())
 }
@@ -52,10 +321,176 @@ pub struct InitBank<'info> {
     )]
     pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
 
+    #[account(
+        init,
+        token::mint = mint,
+        token::authority = insurance_fund,
+        payer = signer,
+        seeds = [b"insurance", mint.key().as_ref()],
+        bump
+    )]
+    pub insurance_fund: InterfaceAccount<'info, TokenAccount>,
+
+    // Mints cannot be closed, so a relisted mint reuses the receipt mint of its previous bank.
+    #[account(
+        init_if_needed,
+        mint::decimals = mint.decimals,
+        mint::authority = bank,
+        mint::token_program = token_program,
+        payer = signer,
+        seeds = [b"receipt", mint.key().as_ref()],
+        bump,
+        constraint = receipt_mint.supply == 0 @ ErrorCode::ReceiptMintInUse,
+    )]
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        init,
+        token::mint = receipt_mint,
+        token::authority = receipt_vault,
+        payer = signer,
+        seeds = [b"receipt_vault", mint.key().as_ref()],
+        bump
+    )]
+    pub receipt_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct UpdateBankConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateOracleConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateIsolationConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateEModeConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFixedTermConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateInterestRateModel<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFeeReceiver<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCaps<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFlashLoanFee<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePauseGuardian<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+#[derive(Accounts)]
+pub struct Pause<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = signer.key() == bank.pause_guardian
+            || signer.key() == bank.authority @ ErrorCode::InvalidPauseAuthority,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
 #[derive(Accounts)]
 pub struct InitUser<'info> {
     #[account(mut)]
@@ -64,7 +499,7 @@ pub struct InitUser<'info> {
     #[account(
         init,
         payer = signer,
-        space = 8 + Bank::INIT_SPACE,
+        space = 8 + User::INIT_SPACE,
         seeds = [signer.key().as_ref()],
         bump
     )]
@@ -0,0 +1,117 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+use crate::error::ErrorCode;
+use crate::events::BadDebtSettled;
+use crate::interest::accrue_bank_interest;
+use crate::state::{Bank, FixedBorrow, User};
+
+pub fn process_settle_bad_debt(ctx: Context<SettleBadDebt>) -> Result<()> {
+    let bank = &mut ctx.accounts.bank;
+    accrue_bank_interest(bank, Clock::get()?.unix_timestamp)?;
+
+    let borrower = &mut ctx.accounts.borrower_account;
+
+    if borrower.positions.iter().any(|position| position.deposited_shares > 0) {
+        return Err(ErrorCode::BorrowerHasCollateral.into());
+    }
+
+    let borrowed_shares = borrower.position(&bank.key())?.borrowed_shares;
+    let fixed_principal = borrower.position(&bank.key())?.fixed_borrow.principal;
+
+    if borrowed_shares == 0 && fixed_principal == 0 {
+        return Err(ErrorCode::NoBadDebt.into());
+    }
+
+    // Unpaid fixed-term interest was never credited to suppliers, so only principal is lost.
+    let debt = bank
+        .borrow_shares_to_amount(borrowed_shares)?
+        .checked_add(fixed_principal)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let covered = debt.min(ctx.accounts.insurance_fund.amount);
+    let shortfall = debt - covered;
+
+    let position = borrower.position_mut(&bank.key())?;
+    position.borrowed_shares = 0;
+    position.fixed_borrow = FixedBorrow::default();
+    borrower.close_empty_positions();
+
+    bank.total_borrowed_shares -= borrowed_shares;
+    bank.total_fixed_borrowed -= fixed_principal;
+    bank.update_totals()?;
+    bank.socialize_loss(shortfall)?;
+
+    emit!(BadDebtSettled {
+        borrower: borrower.owner,
+        bank: bank.key(),
+        debt,
+        shares: borrowed_shares,
+        covered,
+        socialized: shortfall,
+        supply_index: bank.supply_index,
+    });
+
+    if covered == 0 {
+        return Ok(());
+    }
+
+    let transfer_cpi_accounts = TransferChecked {
+        from: ctx.accounts.insurance_fund.to_account_info(),
+        to: ctx.accounts.bank_token_account.to_account_info(),
+        mint: ctx.accounts.mint.to_account_info(),
+        authority: ctx.accounts.insurance_fund.to_account_info(),
+    };
+
+    let mint_key = ctx.accounts.mint.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"insurance",
+        mint_key.as_ref(),
+        &[ctx.bumps.insurance_fund],
+    ]];
+
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_accounts).with_signer(signer_seeds);
+    let decimals = ctx.accounts.mint.decimals;
+
+    transfer_checked(cpi_ctx, covered, decimals)
+}
+
+#[derive(Accounts)]
+pub struct SettleBadDebt<'info> {
+    pub signer: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance", mint.key().as_ref()],
+        bump,
+    )]
+    pub insurance_fund: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Only used to derive the borrower's user account
+    pub borrower: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [borrower.key().as_ref()],
+        bump,
+    )]
+    pub borrower_account: Account<'info, User>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
@@ -1,73 +1,234 @@
-use std::f64::consts::E;
-
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
     token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
 };
-use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 
 use crate::{
-    constants::{MAX_AGE, SOL_USD_FEED_ID, USDC_USD_FEED_ID},
+    constants::PAUSE_BORROW,
     error::ErrorCode,
-    state::{Bank, User},
+    events::{Borrowed, FixedBorrowed},
+    extensions::check_no_transfer_fee,
+    fixed_term::fixed_rate,
+    instructions::shared::{payout_account, unwrap_sol},
+    health::{compute_health, load_priced_banks, upsert_priced_bank, Health},
+    price::read_price,
+    interest::accrue_bank_interest,
+    math::{face_value, Rounding},
+    state::{Bank, BorrowAllowance, FallbackPrice, FixedBorrow, User},
 };
 
 pub fn process_borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
+    let clock = Clock::get()?;
+
     let bank = &mut ctx.accounts.bank;
     let user = &mut ctx.accounts.user_account;
 
-    let price_update = &mut ctx.accounts.price_update;
-
-    let total_collateral: u64;
-
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            let sol_feed_id = get_feed_id_from_hex(SOL_USD_FEED_ID).unwrap();
-            let sol_price =
-                price_update.get_price_no_older_than(&Clock::get()?, MAX_AGE, &sol_feed_id)?;
-
-            let new_value = calculate_accrued_interest(
-                user.deposited_sol,
-                bank.interest_rate,
-                user.last_updated,
-            )?;
-
-            total_collateral = sol_price.price as u64 * new_value;
-        }
-        _ => {
-            let usdc_feed_id = get_feed_id_from_hex(USDC_USD_FEED_ID).unwrap();
-            let usdc_price =
-                price_update.get_price_no_older_than(&Clock::get()?, MAX_AGE, &usdc_feed_id)?;
-
-            let new_value = calculate_accrued_interest(
-                user.deposited_usdc,
-                bank.interest_rate,
-                user.last_updated,
-            )?;
-
-            total_collateral = usdc_price.price as u64 * new_value;
-        }
+    bank.check_not_paused(PAUSE_BORROW)?;
+    accrue_bank_interest(bank, clock.unix_timestamp)?;
+
+    spend_borrow_allowance(&ctx.accounts.signer, user, ctx.accounts.borrow_allowance.as_deref_mut(), amount)?;
+
+    let user_shares = bank.amount_to_borrow_shares(amount, Rounding::Up)?;
+
+    user.position_or_open(&bank.key())?.borrowed_shares += user_shares;
+
+    bank.total_borrowed_shares += user_shares;
+    bank.update_totals()?;
+
+    bank.check_borrow_cap()?;
+
+    let health = check_borrow_health(&ctx, &clock)?;
+    charge_isolated_debt(ctx.accounts, &health, amount)?;
+
+    emit!(Borrowed {
+        user: ctx.accounts.user_account.owner,
+        signer: ctx.accounts.signer.key(),
+        bank: ctx.accounts.bank.key(),
+        amount,
+        shares: user_shares,
+        borrow_index: ctx.accounts.bank.borrow_index,
+        health_factor: health.health_factor()?,
+    });
+
+    send_borrowed(&ctx, amount)
+}
+
+// Opens a fixed-term loan maturing `term` seconds from now at a rate locked from the
+// post-borrow utilization. Each user holds at most one fixed-term loan per bank, and only
+// the owner can open it.
+pub fn process_borrow_fixed(ctx: Context<Borrow>, amount: u64, term: i64) -> Result<()> {
+    let clock = Clock::get()?;
+
+    let bank = &mut ctx.accounts.bank;
+    let user = &mut ctx.accounts.user_account;
+
+    bank.check_not_paused(PAUSE_BORROW)?;
+    accrue_bank_interest(bank, clock.unix_timestamp)?;
+
+    // A fixed-term loan commits the owner to a rate, term and penalties, which a borrow
+    // allowance does not delegate.
+    if ctx.accounts.signer.key() != user.owner {
+        return Err(ErrorCode::FixedBorrowOwnerOnly.into());
+    }
+
+    if bank.fixed_term.max_term == 0 {
+        return Err(ErrorCode::FixedTermDisabled.into());
+    }
+
+    check_no_transfer_fee(&ctx.accounts.mint.to_account_info())?;
+
+    if term <= 0 || term > bank.fixed_term.max_term {
+        return Err(ErrorCode::InvalidFixedTerm.into());
+    }
+
+    let position = user.position_or_open(&bank.key())?;
+
+    if position.fixed_borrow.principal > 0 {
+        return Err(ErrorCode::FixedBorrowExists.into());
     }
 
-    let borrowable_amount = total_collateral
-        .checked_mul(bank.liquidation_threshold)
-        .unwrap();
+    bank.total_fixed_borrowed = bank
+        .total_fixed_borrowed
+        .checked_add(amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    bank.check_borrow_cap()?;
+
+    let fixed_borrow = FixedBorrow {
+        principal: amount,
+        accrued: 0,
+        rate: fixed_rate(bank)?,
+        last_accrued: clock.unix_timestamp,
+        maturity: clock
+            .unix_timestamp
+            .checked_add(term)
+            .ok_or(ErrorCode::MathOverflow)?,
+        early_repay_penalty: bank.fixed_term.early_repay_penalty,
+        late_penalty_rate: bank.fixed_term.late_penalty_rate,
+    };
+
+    position.fixed_borrow = fixed_borrow;
 
-    if borrowable_amount < amount {
+    let health = check_borrow_health(&ctx, &clock)?;
+    charge_isolated_debt(ctx.accounts, &health, amount)?;
+
+    emit!(FixedBorrowed {
+        user: ctx.accounts.user_account.owner,
+        signer: ctx.accounts.signer.key(),
+        bank: ctx.accounts.bank.key(),
+        amount,
+        rate: fixed_borrow.rate,
+        maturity: fixed_borrow.maturity,
+        health_factor: health.health_factor()?,
+    });
+
+    send_borrowed(&ctx, amount)
+}
+
+// Owners borrow freely; anyone else needs an allowance from the owner for this bank, which
+// the borrow draws down.
+fn spend_borrow_allowance(
+    signer: &Signer,
+    user: &User,
+    borrow_allowance: Option<&mut BorrowAllowance>,
+    amount: u64,
+) -> Result<()> {
+    if signer.key() == user.owner {
+        return Ok(());
+    }
+
+    let borrow_allowance = borrow_allowance.ok_or(ErrorCode::InvalidBorrowDelegate)?;
+
+    borrow_allowance.amount = borrow_allowance
+        .amount
+        .checked_sub(amount)
+        .ok_or(ErrorCode::BorrowAllowanceExceeded)?;
+
+    Ok(())
+}
+
+fn check_borrow_health(ctx: &Context<Borrow>, clock: &Clock) -> Result<Health> {
+    let bank = &ctx.accounts.bank;
+
+    let price = read_price(
+        &ctx.accounts.price_update,
+        ctx.accounts.fallback_price.as_deref(),
+        bank,
+        &bank.key(),
+        clock,
+    )?;
+    let mut priced_banks = load_priced_banks(ctx.remaining_accounts, clock)?;
+    upsert_priced_bank(&mut priced_banks, bank.key(), bank, price);
+
+    let health = compute_health(&ctx.accounts.user_account, &priced_banks)?;
+
+    if !health.isolated_banks.is_empty() && !bank.isolation.borrowable_in_isolation {
+        return Err(ErrorCode::NotBorrowableInIsolation.into());
+    }
+
+    if health.isolated_banks.len() > 1 {
+        return Err(ErrorCode::MultipleIsolatedCollateral.into());
+    }
+
+    if !health.can_borrow() {
         return Err(ErrorCode::OverBorrowableAmount.into());
     }
 
+    Ok(health)
+}
+
+// Counts new debt against the isolated bank backing it. The ceiling caps the bank's total
+// across every obligation, not each obligation on its own.
+fn charge_isolated_debt(accounts: &mut Borrow, health: &Health, amount: u64) -> Result<()> {
+    let Some(isolated_bank_key) = health.isolated_banks.first() else {
+        return Ok(());
+    };
+
+    let isolated_bank = accounts
+        .isolated_bank
+        .as_deref_mut()
+        .filter(|isolated_bank| isolated_bank.key() == *isolated_bank_key)
+        .ok_or(ErrorCode::InvalidIsolatedBank)?;
+
+    let debt = face_value(amount, accounts.bank.mint_decimals, Rounding::Up)?;
+
+    isolated_bank.isolated_debt = isolated_bank
+        .isolated_debt
+        .checked_add(debt)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    if isolated_bank.isolated_debt > isolated_bank.isolation.debt_ceiling {
+        return Err(ErrorCode::DebtCeilingExceeded.into());
+    }
+
+    let position = accounts.user_account.position_mut(isolated_bank_key)?;
+    position.isolated_debt = position
+        .isolated_debt
+        .checked_add(debt)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    Ok(())
+}
+
+fn send_borrowed(ctx: &Context<Borrow>, amount: u64) -> Result<()> {
+    let mint_key = ctx.accounts.mint.key();
+    let payout_account = payout_account(
+        &mint_key,
+        ctx.accounts.user_token_account.as_deref(),
+        ctx.accounts.unwrap_account.as_deref(),
+    )?;
+
     let cpi_accounts = TransferChecked {
         from: ctx.accounts.bank_token_account.to_account_info(),
-        to: ctx.accounts.user_token_account.to_account_info(),
+        to: payout_account.to_account_info(),
         mint: ctx.accounts.mint.to_account_info(),
         authority: ctx.accounts.bank_token_account.to_account_info(),
     };
 
     let cpi_program = ctx.accounts.token_program.to_account_info();
 
-    let mint_key = ctx.accounts.mint.key();
     let signer_seeds: &[&[&[u8]]] = &[&[
         b"treasury",
         mint_key.as_ref(),
@@ -80,47 +241,19 @@ pub fn process_borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
 
     transfer_checked(cpi_ctx, amount, decimals)?;
 
-    if bank.total_borrowed == 0 {
-        bank.total_borrowed = amount;
-        bank.total_borrowed_shares = amount;
+    if let Some(unwrap_account) = &ctx.accounts.unwrap_account {
+        unwrap_sol(
+            &ctx.accounts.bank,
+            ctx.bumps.bank,
+            unwrap_account,
+            &ctx.accounts.signer,
+            &ctx.accounts.token_program,
+        )?;
     }
 
-    let borrow_ratio = amount.checked_div(bank.total_borrowed).unwrap();
-    let user_shares = bank
-        .total_borrowed_shares
-        .checked_mul(borrow_ratio)
-        .unwrap();
-
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            user.borrowed_usdc += amount;
-            user.borrowed_usdc_shares += user_shares;
-        }
-        _ => {
-            user.borrowed_sol += amount;
-            user.borrowed_sol_shares += user_shares;
-        }
-    };
-
-    user.last_updated_borrow = Clock::get()?.unix_timestamp;
-
     Ok(())
 }
 
-pub fn calculate_accrued_interest(
-    deposited_value: u64,
-    interest_rate: u64,
-    last_updated: i64,
-) -> Result<u64> {
-    let current_time = Clock::get()?.unix_timestamp;
-    let time_diff = current_time - last_updated;
-
-    let new_value =
-        (deposited_value as f64 * E.powf(interest_rate as f64 / 100.0 * time_diff as f64)) as u64;
-
-    Ok(new_value)
-}
-
 #[derive(Accounts)]
 pub struct Borrow<'info> {
     #[account(mut)]
@@ -142,13 +275,21 @@ pub struct Borrow<'info> {
     )]
     pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
 
+    // The owner's account; delegates borrow against it through `borrow_allowance`.
     #[account(
         mut,
-        seeds = [signer.key().as_ref()],
+        seeds = [user_account.owner.as_ref()],
         bump,
     )]
     pub user_account: Account<'info, User>,
 
+    #[account(
+        mut,
+        seeds = [b"borrow_allowance", user_account.owner.as_ref(), bank.key().as_ref(), signer.key().as_ref()],
+        bump,
+    )]
+    pub borrow_allowance: Option<Account<'info, BorrowAllowance>>,
+
     #[account(
         init_if_needed,
         payer = signer,
@@ -156,10 +297,32 @@ pub struct Borrow<'info> {
         associated_token::authority = signer,
         associated_token::token_program = token_program,
     )]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    // Temporary account native SOL is paid out through; pass it for the native mint only.
+    #[account(
+        init,
+        payer = signer,
+        seeds = [b"unwrap", signer.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = bank,
+        token::token_program = token_program,
+    )]
+    pub unwrap_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     pub price_update: Account<'info, PriceUpdateV2>,
 
+    #[account(address = bank.oracle.fallback_oracle @ ErrorCode::InvalidFallbackOracle)]
+    pub fallback_price: Option<Account<'info, FallbackPrice>>,
+
+    // The isolated bank holding the obligation's collateral, when it has one.
+    #[account(
+        mut,
+        constraint = isolated_bank.key() != bank.key() @ ErrorCode::InvalidIsolatedBank,
+    )]
+    pub isolated_bank: Option<Box<Account<'info, Bank>>>,
+
     pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
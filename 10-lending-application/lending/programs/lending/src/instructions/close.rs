@@ -0,0 +1,191 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
+    TransferChecked,
+};
+
+use crate::error::ErrorCode;
+use crate::events::{BankClosed, UserClosed};
+use crate::state::{Bank, FallbackPrice, User};
+
+pub fn process_close_user(ctx: Context<CloseUser>) -> Result<()> {
+    if ctx.accounts.user_account.positions.iter().any(|position| !position.is_empty()) {
+        return Err(ErrorCode::OpenPositions.into());
+    }
+
+    emit!(UserClosed {
+        user: ctx.accounts.signer.key(),
+    });
+
+    Ok(())
+}
+
+// Delists an empty bank. Whatever the treasury still holds once every share is gone is rounding
+// dust, and the insurance fund is only ever filled from protocol reserves, so both balances are
+// swept to the authority's token account before the accounts are closed. The receipt mint cannot
+// be closed; `init_bank` picks it up again if the mint is relisted.
+pub fn process_close_bank(ctx: Context<CloseBank>) -> Result<()> {
+    let bank = &ctx.accounts.bank;
+
+    if bank.total_deposited_shares > 0
+        || bank.total_borrowed_shares > 0
+        || bank.total_fixed_borrowed > 0
+        || bank.protocol_reserves > 0
+        || bank.flash_loan_amount > 0
+    {
+        return Err(ErrorCode::BankNotEmpty.into());
+    }
+
+    let mint_key = ctx.accounts.mint.key();
+    let treasury_seeds: &[&[u8]] = &[b"treasury", mint_key.as_ref(), &[ctx.bumps.bank_token_account]];
+    let insurance_seeds: &[&[u8]] = &[b"insurance", mint_key.as_ref(), &[ctx.bumps.insurance_fund]];
+    let receipt_vault_seeds: &[&[u8]] = &[b"receipt_vault", mint_key.as_ref(), &[ctx.bumps.receipt_vault]];
+
+    let accounts = &ctx.accounts;
+
+    emit!(BankClosed {
+        bank: accounts.bank.key(),
+        mint: mint_key,
+        authority: accounts.authority.key(),
+        swept_treasury: accounts.bank_token_account.amount,
+        swept_insurance: accounts.insurance_fund.amount,
+    });
+
+    // Rounding dust left in the treasury and any unused insurance go to the authority.
+    sweep_to_authority(accounts, &accounts.bank_token_account, treasury_seeds)?;
+    sweep_to_authority(accounts, &accounts.insurance_fund, insurance_seeds)?;
+
+    close_to_authority(accounts, &accounts.bank_token_account, treasury_seeds)?;
+    close_to_authority(accounts, &accounts.insurance_fund, insurance_seeds)?;
+    close_to_authority(accounts, &accounts.receipt_vault, receipt_vault_seeds)?;
+
+    close_fallback_price(&accounts.fallback_price, &accounts.authority)
+}
+
+// The fallback price account only exists once a price has been posted.
+fn close_fallback_price<'info>(fallback_price: &AccountInfo<'info>, authority: &AccountInfo<'info>) -> Result<()> {
+    if fallback_price.data_is_empty() {
+        return Ok(());
+    }
+
+    if *fallback_price.owner != crate::ID {
+        return Err(anchor_lang::error::ErrorCode::AccountOwnedByWrongProgram.into());
+    }
+
+    FallbackPrice::try_deserialize(&mut &fallback_price.try_borrow_data()?[..])?;
+
+    authority.add_lamports(fallback_price.lamports())?;
+    fallback_price.sub_lamports(fallback_price.lamports())?;
+
+    fallback_price.assign(&System::id());
+    fallback_price.realloc(0, false).map_err(Into::into)
+}
+
+fn sweep_to_authority<'info>(
+    accounts: &CloseBank<'info>,
+    token_account: &InterfaceAccount<'info, TokenAccount>,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    if token_account.amount == 0 {
+        return Ok(());
+    }
+
+    let transfer_cpi_accounts = TransferChecked {
+        from: token_account.to_account_info(),
+        to: accounts.authority_token_account.to_account_info(),
+        mint: accounts.mint.to_account_info(),
+        authority: token_account.to_account_info(),
+    };
+
+    let signer_seeds: &[&[&[u8]]] = &[seeds];
+    let cpi_program = accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_accounts).with_signer(signer_seeds);
+
+    transfer_checked(cpi_ctx, token_account.amount, accounts.mint.decimals)
+}
+
+fn close_to_authority<'info>(
+    accounts: &CloseBank<'info>,
+    token_account: &InterfaceAccount<'info, TokenAccount>,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    let close_cpi_accounts = CloseAccount {
+        account: token_account.to_account_info(),
+        destination: accounts.authority.to_account_info(),
+        authority: token_account.to_account_info(),
+    };
+
+    let signer_seeds: &[&[&[u8]]] = &[seeds];
+    let cpi_program = accounts.token_program.to_account_info();
+
+    close_account(CpiContext::new(cpi_program, close_cpi_accounts).with_signer(signer_seeds))
+}
+
+#[derive(Accounts)]
+pub struct CloseUser<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        close = signer,
+        seeds = [signer.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Account<'info, User>,
+}
+
+#[derive(Accounts)]
+pub struct CloseBank<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority,
+        seeds = [mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Box<Account<'info, Bank>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance", mint.key().as_ref()],
+        bump,
+    )]
+    pub insurance_fund: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"receipt_vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub receipt_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: The bank's fallback price PDA, closed by the handler if it was ever created
+    #[account(
+        mut,
+        seeds = [b"fallback_price", bank.key().as_ref()],
+        bump,
+    )]
+    pub fallback_price: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = authority,
+    )]
+    pub authority_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
@@ -0,0 +1,209 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::constants::PAUSE_WITHDRAW;
+use crate::error::ErrorCode;
+use crate::events::{CollateralDeposited, CollateralWithdrawn};
+use crate::health::{compute_health, load_priced_banks, upsert_priced_bank};
+use crate::instructions::shared::release_emptied_isolated_debt;
+use crate::interest::accrue_bank_interest;
+use crate::price::read_price;
+use crate::state::{Bank, FallbackPrice, User};
+
+pub fn process_deposit_collateral(ctx: Context<DepositCollateral>, shares: u64) -> Result<()> {
+    if shares == 0 {
+        return Err(ErrorCode::ZeroShares.into());
+    }
+
+    let transfer_cpi_accounts = TransferChecked {
+        from: ctx.accounts.user_receipt_account.to_account_info(),
+        to: ctx.accounts.receipt_vault.to_account_info(),
+        mint: ctx.accounts.receipt_mint.to_account_info(),
+        authority: ctx.accounts.signer.to_account_info(),
+    };
+
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_accounts);
+    let decimals = ctx.accounts.receipt_mint.decimals;
+
+    transfer_checked(cpi_ctx, shares, decimals)?;
+
+    let bank_key = ctx.accounts.bank.key();
+    let position = ctx.accounts.user_account.position_or_open(&bank_key)?;
+
+    position.deposited_shares += shares;
+
+    emit!(CollateralDeposited {
+        user: ctx.accounts.signer.key(),
+        bank: bank_key,
+        shares,
+    });
+
+    Ok(())
+}
+
+pub fn process_withdraw_collateral(ctx: Context<WithdrawCollateral>, shares: u64) -> Result<()> {
+    if shares == 0 {
+        return Err(ErrorCode::ZeroShares.into());
+    }
+
+    let clock = Clock::get()?;
+
+    let bank = &mut ctx.accounts.bank;
+    bank.check_not_paused(PAUSE_WITHDRAW)?;
+    accrue_bank_interest(bank, clock.unix_timestamp)?;
+
+    let user = &mut ctx.accounts.user_account;
+    let deposited_shares = user.position(&bank.key())?.deposited_shares;
+
+    if shares > deposited_shares {
+        return Err(ErrorCode::InsufficientFunds.into());
+    }
+
+    user.position_mut(&bank.key())?.deposited_shares -= shares;
+    release_emptied_isolated_debt(user, bank)?;
+
+    let health_factor = if user.positions.iter().any(|position| position.has_debt()) {
+        let price = read_price(
+            &ctx.accounts.price_update,
+            ctx.accounts.fallback_price.as_deref(),
+            bank,
+            &bank.key(),
+            &clock,
+        )?;
+        let mut priced_banks = load_priced_banks(ctx.remaining_accounts, &clock)?;
+        upsert_priced_bank(&mut priced_banks, bank.key(), bank, price);
+
+        let health = compute_health(user, &priced_banks)?;
+
+        if !health.can_borrow() {
+            return Err(ErrorCode::UnhealthyPosition.into());
+        }
+
+        health.health_factor()?
+    } else {
+        u128::MAX
+    };
+
+    user.close_empty_positions();
+
+    emit!(CollateralWithdrawn {
+        user: ctx.accounts.signer.key(),
+        bank: bank.key(),
+        shares,
+        health_factor,
+    });
+
+    let transfer_cpi_accounts = TransferChecked {
+        from: ctx.accounts.receipt_vault.to_account_info(),
+        to: ctx.accounts.user_receipt_account.to_account_info(),
+        mint: ctx.accounts.receipt_mint.to_account_info(),
+        authority: ctx.accounts.receipt_vault.to_account_info(),
+    };
+
+    let mint_key = ctx.accounts.mint.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"receipt_vault",
+        mint_key.as_ref(),
+        &[ctx.bumps.receipt_vault],
+    ]];
+
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_accounts).with_signer(signer_seeds);
+    let decimals = ctx.accounts.receipt_mint.decimals;
+
+    transfer_checked(cpi_ctx, shares, decimals)
+}
+
+#[derive(Accounts)]
+pub struct DepositCollateral<'info> {
+    pub signer: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds=[mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(address = bank.receipt_mint)]
+    pub receipt_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds=[b"receipt_vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub receipt_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds=[signer.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Account<'info, User>,
+
+    #[account(
+        mut,
+        token::mint = receipt_mint,
+        token::authority = signer,
+    )]
+    pub user_receipt_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawCollateral<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds=[mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(address = bank.receipt_mint)]
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds=[b"receipt_vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub receipt_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds=[signer.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Account<'info, User>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+    )]
+    pub user_receipt_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    #[account(address = bank.oracle.fallback_oracle @ ErrorCode::InvalidFallbackOracle)]
+    pub fallback_price: Option<Account<'info, FallbackPrice>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
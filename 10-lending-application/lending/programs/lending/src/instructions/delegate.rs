@@ -0,0 +1,81 @@
+use anchor_lang::prelude::*;
+
+use crate::events::{BorrowDelegateApproved, BorrowDelegateRevoked};
+use crate::state::{Bank, BorrowAllowance, User};
+
+// Sets the delegate's remaining allowance, replacing any previous one. Zero stops further
+// borrows; revoking also reclaims the allowance account's rent.
+pub fn process_approve_borrow_delegate(ctx: Context<ApproveBorrowDelegate>, amount: u64) -> Result<()> {
+    let borrow_allowance = &mut ctx.accounts.borrow_allowance;
+
+    borrow_allowance.owner = ctx.accounts.owner.key();
+    borrow_allowance.bank = ctx.accounts.bank.key();
+    borrow_allowance.delegate = ctx.accounts.delegate.key();
+    borrow_allowance.amount = amount;
+
+    emit!(BorrowDelegateApproved {
+        owner: borrow_allowance.owner,
+        bank: borrow_allowance.bank,
+        delegate: borrow_allowance.delegate,
+        amount,
+    });
+
+    Ok(())
+}
+
+pub fn process_revoke_borrow_delegate(ctx: Context<RevokeBorrowDelegate>) -> Result<()> {
+    emit!(BorrowDelegateRevoked {
+        owner: ctx.accounts.owner.key(),
+        bank: ctx.accounts.bank.key(),
+        delegate: ctx.accounts.delegate.key(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ApproveBorrowDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [owner.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Account<'info, User>,
+
+    pub bank: Account<'info, Bank>,
+
+    /// CHECK: Any key can be approved as a delegate
+    pub delegate: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + BorrowAllowance::INIT_SPACE,
+        seeds = [b"borrow_allowance", owner.key().as_ref(), bank.key().as_ref(), delegate.key().as_ref()],
+        bump,
+    )]
+    pub borrow_allowance: Account<'info, BorrowAllowance>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeBorrowDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub bank: Account<'info, Bank>,
+
+    /// CHECK: Only used to derive the allowance account
+    pub delegate: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"borrow_allowance", owner.key().as_ref(), bank.key().as_ref(), delegate.key().as_ref()],
+        bump,
+    )]
+    pub borrow_allowance: Account<'info, BorrowAllowance>,
+}
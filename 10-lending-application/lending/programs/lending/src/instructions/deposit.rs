@@ -1,57 +1,67 @@
+use crate::constants::PAUSE_DEPOSIT;
+use crate::error::ErrorCode;
+use crate::events::Deposited;
+use crate::health::health_factor_after;
+use crate::instructions::shared::{mint_receipts, receive_funds};
+use crate::interest::accrue_bank_interest;
+use crate::math::Rounding;
 use crate::state::{Bank, User};
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
 pub fn process_deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
-    let transfer_cpi_accounts = TransferChecked {
-        from: ctx.accounts.user_token_account.to_account_info(),
-        to: ctx.accounts.bank_token_account.to_account_info(),
-        mint: ctx.accounts.mint.to_account_info(),
-        authority: ctx.accounts.signer.to_account_info(),
-    };
+    ctx.accounts.bank.check_not_paused(PAUSE_DEPOSIT)?;
 
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_accounts);
+    let clock = Clock::get()?;
+    accrue_bank_interest(&mut ctx.accounts.bank, clock.unix_timestamp)?;
 
-    let decimals = ctx.accounts.mint.decimals;
-
-    transfer_checked(cpi_ctx, amount, decimals)?;
+    let received = receive_funds(
+        &ctx.accounts.signer,
+        &ctx.accounts.mint,
+        ctx.accounts.user_token_account.as_deref(),
+        &mut ctx.accounts.bank_token_account,
+        amount,
+        &ctx.accounts.system_program,
+        &ctx.accounts.token_program,
+    )?;
 
     let bank = &mut ctx.accounts.bank;
 
-    if bank.total_deposits == 0 {
-        bank.total_deposits = amount;
-        bank.total_deposited_shares = amount;
-    }
+    let user_shares = bank.amount_to_deposit_shares(received, Rounding::Down)?;
 
-    let deposit_ratio = amount.checked_div(bank.total_deposits).unwrap();
-    let user_shares = bank
-        .total_deposited_shares
-        .checked_mul(deposit_ratio)
-        .unwrap();
+    if user_shares == 0 {
+        return Err(ErrorCode::ZeroShares.into());
+    }
 
     let user = &mut ctx.accounts.user_account;
+    let position = user.position_or_open(&bank.key())?;
 
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            user.deposited_usdc += amount;
-            user.deposited_usdc_shares += user_shares;
-        }
-        _ => {
-            user.deposited_sol += amount;
-            user.deposited_sol_shares += user_shares;
-        }
-    }
+    position.deposited_shares += user_shares;
 
-    bank.total_deposits += amount;
     bank.total_deposited_shares += user_shares;
-
-    user.last_updated = Clock::get()?.unix_timestamp;
-
-    Ok(())
+    bank.update_totals()?;
+    bank.check_deposit_cap()?;
+
+    emit!(Deposited {
+        user: ctx.accounts.signer.key(),
+        bank: bank.key(),
+        amount: received,
+        shares: user_shares,
+        supply_index: bank.supply_index,
+        health_factor: health_factor_after(user, bank.key(), bank, ctx.remaining_accounts, &clock),
+    });
+
+    mint_receipts(
+        &ctx.accounts.bank,
+        ctx.bumps.bank,
+        &ctx.accounts.receipt_mint,
+        &ctx.accounts.receipt_vault,
+        user_shares,
+        &ctx.accounts.token_program,
+    )
 }
 
 #[derive(Accounts)]
@@ -84,11 +94,25 @@ pub struct Deposit<'info> {
 
     #[account(
         mut,
+        address = bank.receipt_mint,
+    )]
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds=[b"receipt_vault", mint.key().as_ref()],
+        bump
+    )]
+    pub receipt_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
         associated_token::mint = mint,
         associated_token::authority = signer,
         associated_token::token_program = token_program,
     )]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
 
     pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
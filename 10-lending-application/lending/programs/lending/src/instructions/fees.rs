@@ -0,0 +1,145 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+use crate::error::ErrorCode;
+use crate::events::{InsuranceFunded, ProtocolFeesCollected};
+use crate::interest::accrue_bank_interest;
+use crate::state::Bank;
+
+pub fn process_collect_protocol_fees(ctx: Context<CollectProtocolFees>) -> Result<()> {
+    let bank = &mut ctx.accounts.bank;
+    accrue_bank_interest(bank, Clock::get()?.unix_timestamp)?;
+
+    let amount = bank
+        .protocol_reserves
+        .min(ctx.accounts.bank_token_account.amount);
+
+    bank.protocol_reserves -= amount;
+
+    emit!(ProtocolFeesCollected {
+        bank: bank.key(),
+        authority: ctx.accounts.authority.key(),
+        fee_receiver: bank.fee_receiver,
+        amount,
+    });
+
+    let transfer_cpi_accounts = TransferChecked {
+        from: ctx.accounts.bank_token_account.to_account_info(),
+        to: ctx.accounts.fee_receiver_token_account.to_account_info(),
+        mint: ctx.accounts.mint.to_account_info(),
+        authority: ctx.accounts.bank_token_account.to_account_info(),
+    };
+
+    let mint_key = ctx.accounts.mint.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"treasury",
+        mint_key.as_ref(),
+        &[ctx.bumps.bank_token_account],
+    ]];
+
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_accounts).with_signer(signer_seeds);
+    let decimals = ctx.accounts.mint.decimals;
+
+    transfer_checked(cpi_ctx, amount, decimals)
+}
+
+pub fn process_fund_insurance(ctx: Context<FundInsurance>, amount: u64) -> Result<()> {
+    let bank = &mut ctx.accounts.bank;
+    accrue_bank_interest(bank, Clock::get()?.unix_timestamp)?;
+
+    if amount > bank.protocol_reserves {
+        return Err(ErrorCode::InsufficientReserves.into());
+    }
+
+    bank.protocol_reserves -= amount;
+
+    emit!(InsuranceFunded {
+        bank: bank.key(),
+        authority: ctx.accounts.authority.key(),
+        amount,
+    });
+
+    let transfer_cpi_accounts = TransferChecked {
+        from: ctx.accounts.bank_token_account.to_account_info(),
+        to: ctx.accounts.insurance_fund.to_account_info(),
+        mint: ctx.accounts.mint.to_account_info(),
+        authority: ctx.accounts.bank_token_account.to_account_info(),
+    };
+
+    let mint_key = ctx.accounts.mint.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"treasury",
+        mint_key.as_ref(),
+        &[ctx.bumps.bank_token_account],
+    ]];
+
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_accounts).with_signer(signer_seeds);
+    let decimals = ctx.accounts.mint.decimals;
+
+    transfer_checked(cpi_ctx, amount, decimals)
+}
+
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    pub authority: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = bank.fee_receiver,
+        token::token_program = token_program,
+    )]
+    pub fee_receiver_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct FundInsurance<'info> {
+    pub authority: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+        has_one = authority,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"insurance", mint.key().as_ref()],
+        bump,
+    )]
+    pub insurance_fund: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
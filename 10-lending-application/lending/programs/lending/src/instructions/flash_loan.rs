@@ -0,0 +1,208 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::Discriminator;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::constants::{BPS, PAUSE_BORROW};
+use crate::error::ErrorCode;
+use crate::events::{FlashBorrowed, FlashRepaid};
+use crate::interest::accrue_bank_interest;
+use crate::math::{mul_div, to_u64, Rounding};
+use crate::state::Bank;
+
+// Position of `bank` in the `FlashRepay` accounts struct.
+const FLASH_REPAY_BANK_INDEX: usize = 2;
+
+pub fn process_flash_borrow(ctx: Context<FlashBorrow>, amount: u64) -> Result<()> {
+    let bank = &mut ctx.accounts.bank;
+    bank.check_not_paused(PAUSE_BORROW)?;
+    accrue_bank_interest(bank, Clock::get()?.unix_timestamp)?;
+
+    if bank.flash_loan_amount != 0 {
+        return Err(ErrorCode::FlashLoanInProgress.into());
+    }
+
+    let instructions = ctx.accounts.instructions.to_account_info();
+    let current_index = load_current_index_checked(&instructions)? as usize;
+
+    if load_instruction_at_checked(current_index, &instructions)?.program_id != crate::ID {
+        return Err(ErrorCode::FlashLoanCpi.into());
+    }
+
+    let mut index = current_index + 1;
+    let mut found_repay = false;
+
+    while let Ok(instruction) = load_instruction_at_checked(index, &instructions) {
+        if instruction.program_id == crate::ID
+            && instruction.data.starts_with(&crate::instruction::FlashRepay::DISCRIMINATOR)
+            && instruction
+                .accounts
+                .get(FLASH_REPAY_BANK_INDEX)
+                .is_some_and(|account| account.pubkey == bank.key())
+        {
+            found_repay = true;
+            break;
+        }
+
+        index += 1;
+    }
+
+    if !found_repay {
+        return Err(ErrorCode::MissingFlashRepay.into());
+    }
+
+    bank.flash_loan_amount = amount;
+
+    emit!(FlashBorrowed {
+        signer: ctx.accounts.signer.key(),
+        bank: bank.key(),
+        amount,
+    });
+
+    let transfer_cpi_accounts = TransferChecked {
+        from: ctx.accounts.bank_token_account.to_account_info(),
+        to: ctx.accounts.user_token_account.to_account_info(),
+        mint: ctx.accounts.mint.to_account_info(),
+        authority: ctx.accounts.bank_token_account.to_account_info(),
+    };
+
+    let mint_key = ctx.accounts.mint.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"treasury",
+        mint_key.as_ref(),
+        &[ctx.bumps.bank_token_account],
+    ]];
+
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_accounts).with_signer(signer_seeds);
+    let decimals = ctx.accounts.mint.decimals;
+
+    transfer_checked(cpi_ctx, amount, decimals)
+}
+
+pub fn process_flash_repay(ctx: Context<FlashRepay>) -> Result<()> {
+    let bank = &mut ctx.accounts.bank;
+    accrue_bank_interest(bank, Clock::get()?.unix_timestamp)?;
+
+    if bank.flash_loan_amount == 0 {
+        return Err(ErrorCode::NoFlashLoan.into());
+    }
+
+    let amount = bank.flash_loan_amount;
+    let fee = to_u64(mul_div(
+        amount as u128,
+        bank.flash_loan_fee as u128,
+        BPS as u128,
+        Rounding::Up,
+    )?)?;
+
+    let transfer_cpi_accounts = TransferChecked {
+        from: ctx.accounts.user_token_account.to_account_info(),
+        to: ctx.accounts.bank_token_account.to_account_info(),
+        mint: ctx.accounts.mint.to_account_info(),
+        authority: ctx.accounts.signer.to_account_info(),
+    };
+
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_ctx = CpiContext::new(cpi_program, transfer_cpi_accounts);
+    let decimals = ctx.accounts.mint.decimals;
+    let owed = amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+    let balance_before = ctx.accounts.bank_token_account.amount;
+
+    transfer_checked(cpi_ctx, owed, decimals)?;
+
+    // Transfer-fee mints deliver less than was sent; the loan only counts as repaid in full.
+    ctx.accounts.bank_token_account.reload()?;
+    let received = ctx.accounts.bank_token_account.amount - balance_before;
+
+    if received < owed {
+        return Err(ErrorCode::FlashLoanNotRepaid.into());
+    }
+
+    bank.flash_loan_amount = 0;
+    bank.credit_fee(fee)?;
+
+    emit!(FlashRepaid {
+        signer: ctx.accounts.signer.key(),
+        bank: bank.key(),
+        amount,
+        fee,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct FlashBorrow<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Validated by address against the instructions sysvar
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FlashRepay<'info> {
+    pub signer: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [mint.key().as_ref()],
+        bump,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", mint.key().as_ref()],
+        bump,
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = signer,
+        token::token_program = token_program,
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
@@ -1,50 +1,65 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked, transfer_checked}};
-use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 
-use crate::{constants::{MAX_AGE, SOL_USD_FEED_ID, USDC_USD_FEED_ID}, state::{Bank, User}, error::ErrorCode};
+use crate::{constants::{BPS, PAUSE_LIQUIDATE}, instructions::shared::{burn_receipts, release_emptied_isolated_debt, release_isolated_debt}, state::{Bank, FallbackPrice, User}, error::ErrorCode, events::Liquidated, fixed_term::{apply_fixed_repayment, fixed_debt}, health::{compute_health, load_priced_banks, upsert_priced_bank}, interest::accrue_bank_interest, math::{mul_div, to_u64, Rounding}, price::read_price};
 
-use super::calculate_accrued_interest;
-
-pub fn process_liquidate(ctx: Context<Liquidate>) -> Result<()> {
+pub fn process_liquidate(ctx: Context<Liquidate>, repay_amount: u64) -> Result<()> {
     let collateral_bank = &mut ctx.accounts.collateral_bank;
     let borrowed_bank = &mut ctx.accounts.borrowed_bank;
-    let user = &mut ctx.accounts.user_account;
-
-    let price_update = &ctx.accounts.price_update;
+    let borrower = &mut ctx.accounts.borrower_account;
 
-    let sol_feed_id = get_feed_id_from_hex(SOL_USD_FEED_ID)?;
-    let usdc_feed_id = get_feed_id_from_hex(USDC_USD_FEED_ID)?;
+    collateral_bank.check_not_paused(PAUSE_LIQUIDATE)?;
+    borrowed_bank.check_not_paused(PAUSE_LIQUIDATE)?;
 
     let clock = Clock::get()?;
 
-    let sol_price = price_update.get_price_no_older_than(&clock, MAX_AGE, &sol_feed_id)?;
-    let usdc_price = price_update.get_price_no_older_than(&clock, MAX_AGE, &usdc_feed_id)?;
-
-    let total_collateral: u64;
-    let total_borrowed: u64;
+    accrue_bank_interest(collateral_bank, clock.unix_timestamp)?;
+    accrue_bank_interest(borrowed_bank, clock.unix_timestamp)?;
 
-    match ctx.accounts.collateral_mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            let new_usdc = calculate_accrued_interest(user.deposited_usdc, collateral_bank.interest_rate, user.last_updated)?;
-            total_collateral = usdc_price.price as u64 * new_usdc;
+    let collateral_price = read_price(&ctx.accounts.collateral_price_update, ctx.accounts.collateral_fallback_price.as_deref(), collateral_bank, &collateral_bank.key(), &clock)?;
+    let borrowed_price = read_price(&ctx.accounts.borrowed_price_update, ctx.accounts.borrowed_fallback_price.as_deref(), borrowed_bank, &borrowed_bank.key(), &clock)?;
 
-            let new_sol = calculate_accrued_interest(user.borrowed_sol, borrowed_bank.interest_rate, user.last_updated_borrow)?;
-            total_borrowed = sol_price.price as u64 * new_sol;
-        }
-        _ => {
-            let new_sol = calculate_accrued_interest(user.deposited_sol, collateral_bank.interest_rate, user.last_updated)?;
-            total_collateral = sol_price.price as u64 * new_sol;
+    let mut priced_banks = load_priced_banks(ctx.remaining_accounts, &clock)?;
+    upsert_priced_bank(&mut priced_banks, collateral_bank.key(), collateral_bank, collateral_price);
+    upsert_priced_bank(&mut priced_banks, borrowed_bank.key(), borrowed_bank, borrowed_price);
 
-            let new_usdc = calculate_accrued_interest(user.borrowed_usdc, borrowed_bank.interest_rate, user.last_updated_borrow)?;
-            total_borrowed = usdc_price.price as u64 * new_usdc;
-        }
-    }
+    let borrowed_shares = borrower.position(&borrowed_bank.key())?.borrowed_shares;
+    let fixed_borrow = borrower.position(&borrowed_bank.key())?.fixed_borrow;
+    let deposited_shares = borrower.position(&collateral_bank.key())?.deposited_shares;
 
-    let health_factor = total_collateral as f64 * collateral_bank.liquidation_threshold as f64 / total_borrowed as f64;
+    let variable_debt = borrowed_bank.borrow_shares_to_amount(borrowed_shares)?;
+    let fixed_debt = fixed_debt(&fixed_borrow, clock.unix_timestamp)?;
+    let debt = variable_debt.checked_add(fixed_debt).ok_or(ErrorCode::MathOverflow)?;
+    let collateral = collateral_bank.deposit_shares_to_amount(deposited_shares)?;
 
-    if health_factor >= 1.0 {
+    // Unhealthy obligations are liquidated up to the close factor. A healthy obligation can
+    // still be liquidated once a fixed-term loan is past maturity, but only for that loan.
+    let max_repay = if compute_health(borrower, &priced_banks)?.is_liquidatable() {
+        to_u64(mul_div(debt as u128, borrowed_bank.liquidation_close_factor as u128, BPS as u128, Rounding::Down)?)?
+    } else if fixed_borrow.is_matured(clock.unix_timestamp) {
+        fixed_debt
+    } else {
         return Err(ErrorCode::NotUnderCollateralized.into());
+    };
+    let mut repay_amount = repay_amount.min(max_repay);
+
+    let bonus_factor = (BPS + collateral_bank.liquidation_bonus) as u128;
+
+    let repay_value = borrowed_price.value_of(repay_amount, borrowed_bank.mint_decimals)?;
+    let seized_value = mul_div(repay_value, bonus_factor, BPS as u128, Rounding::Down)?;
+    let mut seized_amount = collateral_price.amount_for(seized_value, collateral_bank.mint_decimals)?;
+
+    if seized_amount > collateral {
+        seized_amount = collateral;
+
+        let collateral_value = collateral_price.value_of(collateral, collateral_bank.mint_decimals)?;
+        let capped_repay_value = mul_div(collateral_value, BPS as u128, bonus_factor, Rounding::Down)?;
+        repay_amount = borrowed_price.amount_for(capped_repay_value, borrowed_bank.mint_decimals)?;
+    }
+
+    if repay_amount == 0 {
+        return Err(ErrorCode::LiquidationTooSmall.into());
     }
 
     let transfer_to_bank = TransferChecked {
@@ -57,9 +72,85 @@ pub fn process_liquidate(ctx: Context<Liquidate>) -> Result<()> {
     let cpi_program = ctx.accounts.token_program.to_account_info();
     let cpi_ctx = CpiContext::new(cpi_program, transfer_to_bank);
     let decimals = ctx.accounts.borrowed_mint.decimals;
+    let balance_before = ctx.accounts.borrowed_bank_token_account.amount;
+
+    transfer_checked(cpi_ctx, repay_amount, decimals)?;
 
-    let liquidation_amount = total_borrowed.checked_mul(borrowed_bank.liquidation_close_factor).unwrap();
-    transfer_checked(cpi_ctx, liquidation_amount, decimals)?;
+    // Transfer-fee mints deliver less than `repay_amount`; only repay and reward what arrived.
+    ctx.accounts.borrowed_bank_token_account.reload()?;
+    let received = ctx.accounts.borrowed_bank_token_account.amount - balance_before;
+
+    if received < repay_amount {
+        repay_amount = received;
+
+        let repay_value = borrowed_price.value_of(repay_amount, borrowed_bank.mint_decimals)?;
+        let seized_value = mul_div(repay_value, bonus_factor, BPS as u128, Rounding::Down)?;
+        seized_amount = collateral_price
+            .amount_for(seized_value, collateral_bank.mint_decimals)?
+            .min(collateral);
+    }
+
+    if repay_amount == 0 || seized_amount == 0 {
+        return Err(ErrorCode::LiquidationTooSmall.into());
+    }
+
+    // Fixed-term debt is settled first, interest and late penalties before principal.
+    let fixed_repay_amount = repay_amount.min(fixed_debt);
+    let variable_repay_amount = repay_amount - fixed_repay_amount;
+
+    let repaid_shares = if variable_repay_amount == variable_debt {
+        borrowed_shares
+    } else {
+        borrowed_bank.amount_to_borrow_shares(variable_repay_amount, Rounding::Down)?
+    };
+    let seized_shares = if seized_amount == collateral {
+        deposited_shares
+    } else {
+        collateral_bank.amount_to_deposit_shares(seized_amount, Rounding::Up)?.min(deposited_shares)
+    };
+
+    let borrowed_position = borrower.position_mut(&borrowed_bank.key())?;
+    borrowed_position.borrowed_shares -= repaid_shares;
+    apply_fixed_repayment(borrowed_bank, &mut borrowed_position.fixed_borrow, fixed_repay_amount, clock.unix_timestamp)?;
+
+    borrower.position_mut(&collateral_bank.key())?.deposited_shares -= seized_shares;
+    release_emptied_isolated_debt(borrower, collateral_bank)?;
+
+    // Isolated debt is usually counted against the collateral being seized.
+    let counted_against_collateral = borrower
+        .position(&collateral_bank.key())?
+        .isolated_debt
+        > 0;
+    let isolated_bank = if counted_against_collateral {
+        Some(&mut *collateral_bank)
+    } else {
+        ctx.accounts.isolated_bank.as_deref_mut()
+    };
+    release_isolated_debt(borrower, isolated_bank, borrowed_bank, repay_amount)?;
+
+    borrower.close_empty_positions();
+
+    borrowed_bank.total_borrowed_shares -= repaid_shares;
+    borrowed_bank.update_totals()?;
+
+    collateral_bank.total_deposited_shares -= seized_shares;
+    collateral_bank.update_totals()?;
+
+    upsert_priced_bank(&mut priced_banks, collateral_bank.key(), collateral_bank, collateral_price);
+    upsert_priced_bank(&mut priced_banks, borrowed_bank.key(), borrowed_bank, borrowed_price);
+
+    emit!(Liquidated {
+        liquidator: ctx.accounts.liquidator.key(),
+        borrower: ctx.accounts.borrower.key(),
+        collateral_bank: collateral_bank.key(),
+        borrowed_bank: borrowed_bank.key(),
+        repay_amount,
+        repaid_shares,
+        fixed_repay_amount,
+        seized_amount,
+        seized_shares,
+        health_factor: compute_health(borrower, &priced_banks)?.health_factor()?,
+    });
 
     let transfer_to_liquidator = TransferChecked {
         from: ctx.accounts.collateral_bank_token_account.to_account_info(),
@@ -79,10 +170,16 @@ pub fn process_liquidate(ctx: Context<Liquidate>) -> Result<()> {
         .with_signer(signer_seeds);
     let decimals = ctx.accounts.collateral_mint.decimals;
 
-    let liquidator_amount = liquidation_amount * (100 + collateral_bank.liquidation_bonus) / 100;
-    transfer_checked(cpi_ctx, liquidator_amount, decimals)?;
+    transfer_checked(cpi_ctx, seized_amount, decimals)?;
 
-    Ok(())
+    burn_receipts(
+        &ctx.accounts.collateral_bank,
+        &ctx.accounts.collateral_receipt_mint,
+        &ctx.accounts.collateral_receipt_vault,
+        ctx.bumps.collateral_receipt_vault,
+        seized_shares,
+        &ctx.accounts.token_program,
+    )
 }
 
 #[derive(Accounts)]
@@ -90,7 +187,9 @@ pub struct Liquidate<'info> {
     #[account(mut)]
     pub liquidator: Signer<'info>,
 
-    pub price_update: Account<'info, PriceUpdateV2>,
+    pub collateral_price_update: Account<'info, PriceUpdateV2>,
+
+    pub borrowed_price_update: Account<'info, PriceUpdateV2>,
 
     pub collateral_mint: InterfaceAccount<'info, Mint>,
 
@@ -107,9 +206,16 @@ pub struct Liquidate<'info> {
         mut,
         seeds = [borrowed_mint.key().as_ref()],
         bump,
+        constraint = borrowed_bank.key() != collateral_bank.key() @ ErrorCode::InvalidLiquidationBanks,
     )]
     pub borrowed_bank: Account<'info, Bank>,
 
+    #[account(address = collateral_bank.oracle.fallback_oracle @ ErrorCode::InvalidFallbackOracle)]
+    pub collateral_fallback_price: Option<Account<'info, FallbackPrice>>,
+
+    #[account(address = borrowed_bank.oracle.fallback_oracle @ ErrorCode::InvalidFallbackOracle)]
+    pub borrowed_fallback_price: Option<Account<'info, FallbackPrice>>,
+
     #[account(
         mut, 
         seeds = [b"treasury", collateral_mint.key().as_ref()],
@@ -126,10 +232,26 @@ pub struct Liquidate<'info> {
 
     #[account(
         mut,
-        seeds = [liquidator.key().as_ref()],
+        address = collateral_bank.receipt_mint,
+    )]
+    pub collateral_receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"receipt_vault", collateral_mint.key().as_ref()],
         bump,
     )]
-    pub user_account: Account<'info, User>,
+    pub collateral_receipt_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Only used to derive the borrower's user account
+    pub borrower: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [borrower.key().as_ref()],
+        bump,
+    )]
+    pub borrower_account: Account<'info, User>,
 
     #[account(
         init_if_needed,
@@ -149,6 +271,15 @@ pub struct Liquidate<'info> {
     )]
     pub liquidator_borrowed_token_account: InterfaceAccount<'info, TokenAccount>,
 
+    // The isolated bank the borrower's debt was counted against, when it is not the
+    // collateral bank.
+    #[account(
+        mut,
+        constraint = isolated_bank.key() != collateral_bank.key() @ ErrorCode::InvalidIsolatedBank,
+        constraint = isolated_bank.key() != borrowed_bank.key() @ ErrorCode::InvalidIsolatedBank,
+    )]
+    pub isolated_bank: Option<Box<Account<'info, Bank>>>,
+
     pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
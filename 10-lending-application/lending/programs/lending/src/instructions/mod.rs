@@ -7,6 +7,9 @@ pub mod deposit;
 pub use withdraw::*;
 pub mod withdraw;
 
+pub use collateral::*;
+pub mod collateral;
+
 pub use borrow::*;
 pub mod borrow;
 
@@ -15,3 +18,26 @@ pub mod repay;
 
 pub use liquidate::*;
 pub mod liquidate;
+
+pub use oracle::*;
+pub mod oracle;
+
+pub use fees::*;
+pub mod fees;
+
+pub use bad_debt::*;
+pub mod bad_debt;
+
+pub use flash_loan::*;
+pub mod flash_loan;
+
+pub use delegate::*;
+pub mod delegate;
+
+pub use views::*;
+pub mod views;
+
+pub use close::*;
+pub mod close;
+
+pub mod shared;
@@ -0,0 +1,50 @@
+use anchor_lang::prelude::*;
+
+use crate::events::FallbackPriceUpdated;
+use crate::state::{Bank, FallbackPrice};
+
+pub fn process_update_fallback_price(
+    ctx: Context<UpdateFallbackPrice>,
+    price: i64,
+    exponent: i32,
+    conf: u64,
+) -> Result<()> {
+    let fallback_price = &mut ctx.accounts.fallback_price;
+
+    fallback_price.bank = ctx.accounts.bank.key();
+    fallback_price.price = price;
+    fallback_price.exponent = exponent;
+    fallback_price.conf = conf;
+    fallback_price.publish_time = Clock::get()?.unix_timestamp;
+
+    emit!(FallbackPriceUpdated {
+        bank: fallback_price.bank,
+        authority: ctx.accounts.authority.key(),
+        price,
+        exponent,
+        conf,
+        publish_time: fallback_price.publish_time,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateFallbackPrice<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(has_one = authority)]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + FallbackPrice::INIT_SPACE,
+        seeds = [b"fallback_price", bank.key().as_ref()],
+        bump,
+    )]
+    pub fallback_price: Account<'info, FallbackPrice>,
+
+    pub system_program: Program<'info, System>,
+}
@@ -1,71 +1,119 @@
-use std::f64::consts::E;
-
 use anchor_lang::prelude::*;
-use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked, transfer_checked}};
+use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface}};
 
 use crate::state::{Bank, User};
+use crate::constants::PAUSE_REPAY;
 use crate::error::ErrorCode;
+use crate::events::{FixedRepaid, Repaid};
+use crate::fixed_term::{apply_fixed_repayment, early_repay_penalty, fixed_debt};
+use crate::health::health_factor_after;
+use crate::instructions::shared::{receive_funds, release_isolated_debt};
+use crate::interest::accrue_bank_interest;
+use crate::math::Rounding;
 
-pub fn process_repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
-    let user = &mut ctx.accounts.user_account;
-
-    let borrow_value: u64;
-
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            borrow_value = user.borrowed_usdc;
-        }
-        _ => {
-            borrow_value = user.borrowed_sol;
-        }
-    }
+pub fn process_repay(mut ctx: Context<Repay>, amount: u64) -> Result<()> {
+    let clock = Clock::get()?;
 
-    let time_diff = Clock::get()?.unix_timestamp - user.last_updated_borrow;
     let bank = &mut ctx.accounts.bank;
+    bank.check_not_paused(PAUSE_REPAY)?;
+    accrue_bank_interest(bank, clock.unix_timestamp)?;
 
-    bank.total_borrowed -= (bank.total_borrowed as f64 * E.powf(bank.interest_rate as f64 / 100.0 * time_diff as f64)) as u64;
+    let borrowed_shares = ctx.accounts.user_account.position(&bank.key())?.borrowed_shares;
 
-    let value_per_share = (bank.total_borrowed as f64 / bank.total_borrowed_shares as f64) as u64;
-
-    let user_value = borrow_value / value_per_share;
+    let user_value = bank.borrow_shares_to_amount(borrowed_shares)?;
 
     if amount > user_value {
         return Err(ErrorCode::OverRepay.into());
     }
 
-    let cpi_accounts = TransferChecked {
-        from: ctx.accounts.user_token_account.to_account_info(),
-        to: ctx.accounts.bank_token_account.to_account_info(),
-        authority: ctx.accounts.signer.to_account_info(),
-        mint: ctx.accounts.mint.to_account_info(),
+    let received = transfer_repayment(&mut ctx, amount)?;
+
+    let bank = &mut ctx.accounts.bank;
+    let user = &mut ctx.accounts.user_account;
+
+    let user_shares = if received == user_value {
+        borrowed_shares
+    } else {
+        bank.amount_to_borrow_shares(received, Rounding::Down)?
     };
 
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-    let decimals = ctx.accounts.mint.decimals;
+    user.position_mut(&bank.key())?.borrowed_shares -= user_shares;
+    release_isolated_debt(user, ctx.accounts.isolated_bank.as_deref_mut(), bank, received)?;
+    user.close_empty_positions();
+
+    bank.total_borrowed_shares -= user_shares;
+    bank.update_totals()?;
+
+    emit!(Repaid {
+        user: ctx.accounts.signer.key(),
+        bank: bank.key(),
+        amount: received,
+        shares: user_shares,
+        borrow_index: bank.borrow_index,
+        health_factor: health_factor_after(user, bank.key(), bank, ctx.remaining_accounts, &clock),
+    });
+
+    Ok(())
+}
+
+// Fixed-term loans are repaid in full: principal, accrued interest, late penalties and,
+// before maturity, the early-repay penalty.
+pub fn process_repay_fixed(mut ctx: Context<Repay>) -> Result<()> {
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
 
-    transfer_checked(cpi_ctx, amount, decimals)?;
+    let bank = &mut ctx.accounts.bank;
+    bank.check_not_paused(PAUSE_REPAY)?;
+    accrue_bank_interest(bank, now)?;
 
-    let borrow_ratio = amount.checked_div(bank.total_borrowed).unwrap();
-    let user_shares = bank.total_borrowed_shares.checked_mul(borrow_ratio).unwrap();
+    let fixed_borrow = ctx.accounts.user_account.position(&bank.key())?.fixed_borrow;
 
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            user.borrowed_usdc -= amount;
-            user.borrowed_usdc_shares -= user_shares;
-        }
-        _ => {
-            user.borrowed_sol -= amount;
-            user.borrowed_sol_shares -= user_shares;
-        }
+    if fixed_borrow.principal == 0 {
+        return Err(ErrorCode::NoFixedBorrow.into());
     }
 
-    bank.total_borrowed -= amount;
-    bank.total_borrowed_shares -= user_shares;
+    let debt = fixed_debt(&fixed_borrow, now)?;
+    let penalty = early_repay_penalty(&fixed_borrow, now)?;
+    let amount = debt.checked_add(penalty).ok_or(ErrorCode::MathOverflow)?;
+
+    if transfer_repayment(&mut ctx, amount)? < amount {
+        return Err(ErrorCode::InsufficientFunds.into());
+    }
+
+    let bank = &mut ctx.accounts.bank;
+    let user = &mut ctx.accounts.user_account;
+
+    let position = user.position_mut(&bank.key())?;
+    apply_fixed_repayment(bank, &mut position.fixed_borrow, debt, now)?;
+    bank.credit_fee(penalty)?;
+
+    release_isolated_debt(user, ctx.accounts.isolated_bank.as_deref_mut(), bank, debt)?;
+    user.close_empty_positions();
+
+    emit!(FixedRepaid {
+        user: ctx.accounts.signer.key(),
+        bank: bank.key(),
+        amount,
+        principal: fixed_borrow.principal,
+        early_repay_penalty: penalty,
+        health_factor: health_factor_after(user, bank.key(), bank, ctx.remaining_accounts, &clock),
+    });
 
     Ok(())
 }
 
+fn transfer_repayment(ctx: &mut Context<Repay>, amount: u64) -> Result<u64> {
+    receive_funds(
+        &ctx.accounts.signer,
+        &ctx.accounts.mint,
+        ctx.accounts.user_token_account.as_deref(),
+        &mut ctx.accounts.bank_token_account,
+        amount,
+        &ctx.accounts.system_program,
+        &ctx.accounts.token_program,
+    )
+}
+
 #[derive(Accounts)]
 pub struct Repay<'info> {
     #[account(mut)]
@@ -95,12 +143,20 @@ pub struct Repay<'info> {
     pub user_account: Account<'info, User>,
 
     #[account(
-        mut,
+        init_if_needed,
+        payer = signer,
         associated_token::mint = mint,
         associated_token::authority = signer,
         associated_token::token_program = token_program,
     )]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    // The isolated bank the repaid debt was counted against, when there is one.
+    #[account(
+        mut,
+        constraint = isolated_bank.key() != bank.key() @ ErrorCode::InvalidIsolatedBank,
+    )]
+    pub isolated_bank: Option<Box<Account<'info, Bank>>>,
 
     pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
@@ -0,0 +1,212 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_spl::token::spl_token::native_mint;
+use anchor_spl::token_interface::{
+    burn, close_account, mint_to, sync_native, transfer_checked, Burn, CloseAccount, Mint, MintTo,
+    SyncNative, TokenAccount, TokenInterface, TransferChecked,
+};
+
+use crate::error::ErrorCode;
+use crate::math::{face_value, Rounding};
+use crate::state::{Bank, User};
+
+pub fn is_native_mint(mint: &Pubkey) -> bool {
+    *mint == native_mint::ID
+}
+
+// Moves `amount` from the signer into the treasury and returns what actually arrived. Native SOL
+// is wrapped straight into the treasury from the signer's lamports, so it takes no token
+// account of theirs; other mints come from `user_token_account`.
+pub fn receive_funds<'info>(
+    signer: &Signer<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    user_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    bank_token_account: &mut InterfaceAccount<'info, TokenAccount>,
+    amount: u64,
+    system_program: &Program<'info, System>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<u64> {
+    match (is_native_mint(&mint.key()), user_token_account) {
+        (true, None) => {
+            let transfer_accounts = Transfer {
+                from: signer.to_account_info(),
+                to: bank_token_account.to_account_info(),
+            };
+
+            transfer(
+                CpiContext::new(system_program.to_account_info(), transfer_accounts),
+                amount,
+            )?;
+
+            let sync_accounts = SyncNative {
+                account: bank_token_account.to_account_info(),
+            };
+
+            sync_native(CpiContext::new(token_program.to_account_info(), sync_accounts))?;
+
+            Ok(amount)
+        }
+        (false, Some(user_token_account)) => {
+            let transfer_accounts = TransferChecked {
+                from: user_token_account.to_account_info(),
+                to: bank_token_account.to_account_info(),
+                mint: mint.to_account_info(),
+                authority: signer.to_account_info(),
+            };
+
+            let balance_before = bank_token_account.amount;
+
+            transfer_checked(
+                CpiContext::new(token_program.to_account_info(), transfer_accounts),
+                amount,
+                mint.decimals,
+            )?;
+
+            // Transfer-fee mints deliver less than `amount`; only credit what arrived.
+            bank_token_account.reload()?;
+
+            Ok(bank_token_account.amount - balance_before)
+        }
+        _ => Err(ErrorCode::InvalidUnwrapAccount.into()),
+    }
+}
+
+// Native SOL is paid out through a temporary wSOL account owned by the bank rather than a token
+// account of the recipient's, so wSOL they already hold is never unwrapped and none is created
+// for them. Non-native payouts go straight to `user_token_account`.
+pub fn payout_account<'a, 'info>(
+    mint: &Pubkey,
+    user_token_account: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+    unwrap_account: Option<&'a InterfaceAccount<'info, TokenAccount>>,
+) -> Result<&'a InterfaceAccount<'info, TokenAccount>> {
+    match (is_native_mint(mint), user_token_account, unwrap_account) {
+        (true, None, Some(unwrap_account)) => Ok(unwrap_account),
+        (false, Some(user_token_account), None) => Ok(user_token_account),
+        _ => Err(ErrorCode::InvalidUnwrapAccount.into()),
+    }
+}
+
+// Closes the temporary wSOL account, paying its balance and rent to `recipient` as SOL.
+pub fn unwrap_sol<'info>(
+    bank: &Account<'info, Bank>,
+    bank_bump: u8,
+    unwrap_account: &InterfaceAccount<'info, TokenAccount>,
+    recipient: &Signer<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    let close_accounts = CloseAccount {
+        account: unwrap_account.to_account_info(),
+        destination: recipient.to_account_info(),
+        authority: bank.to_account_info(),
+    };
+
+    let signer_seeds: &[&[&[u8]]] = &[&[bank.mint_address.as_ref(), &[bank_bump]]];
+
+    close_account(
+        CpiContext::new(token_program.to_account_info(), close_accounts).with_signer(signer_seeds),
+    )
+}
+
+// Receipt tokens are minted into the bank's receipt vault, where they back the
+// deposited shares recorded on user positions.
+pub fn mint_receipts<'info>(
+    bank: &Account<'info, Bank>,
+    bank_bump: u8,
+    receipt_mint: &InterfaceAccount<'info, Mint>,
+    receipt_vault: &InterfaceAccount<'info, TokenAccount>,
+    shares: u64,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    let mint_accounts = MintTo {
+        mint: receipt_mint.to_account_info(),
+        to: receipt_vault.to_account_info(),
+        authority: bank.to_account_info(),
+    };
+
+    let signer_seeds: &[&[&[u8]]] = &[&[bank.mint_address.as_ref(), &[bank_bump]]];
+
+    mint_to(
+        CpiContext::new(token_program.to_account_info(), mint_accounts).with_signer(signer_seeds),
+        shares,
+    )
+}
+
+pub fn burn_receipts<'info>(
+    bank: &Account<'info, Bank>,
+    receipt_mint: &InterfaceAccount<'info, Mint>,
+    receipt_vault: &InterfaceAccount<'info, TokenAccount>,
+    receipt_vault_bump: u8,
+    shares: u64,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    let burn_accounts = Burn {
+        mint: receipt_mint.to_account_info(),
+        from: receipt_vault.to_account_info(),
+        authority: receipt_vault.to_account_info(),
+    };
+
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"receipt_vault",
+        bank.mint_address.as_ref(),
+        &[receipt_vault_bump],
+    ]];
+
+    burn(
+        CpiContext::new(token_program.to_account_info(), burn_accounts).with_signer(signer_seeds),
+        shares,
+    )
+}
+
+// Releases repaid debt from the isolated bank it was counted against. Only debt owed to banks
+// borrowable in isolation is counted; once the obligation is debt-free, all of it is released.
+pub fn release_isolated_debt(
+    user: &mut User,
+    isolated_bank: Option<&mut Account<Bank>>,
+    repaid_bank: &Bank,
+    repaid: u64,
+) -> Result<()> {
+    let has_debt = user.positions.iter().any(|position| position.has_debt());
+
+    let Some(position) = user
+        .positions
+        .iter_mut()
+        .find(|position| position.isolated_debt > 0)
+    else {
+        return Ok(());
+    };
+
+    let released = if !has_debt {
+        position.isolated_debt
+    } else if repaid_bank.isolation.borrowable_in_isolation {
+        face_value(repaid, repaid_bank.mint_decimals, Rounding::Down)?.min(position.isolated_debt)
+    } else {
+        0
+    };
+
+    if released == 0 {
+        return Ok(());
+    }
+
+    let isolated_bank = isolated_bank
+        .filter(|isolated_bank| isolated_bank.key() == position.bank)
+        .ok_or(ErrorCode::InvalidIsolatedBank)?;
+
+    position.isolated_debt -= released;
+    isolated_bank.isolated_debt = isolated_bank.isolated_debt.saturating_sub(released);
+
+    Ok(())
+}
+
+// Collateral that leaves an isolated position no longer backs the debt counted against it.
+pub fn release_emptied_isolated_debt(user: &mut User, bank: &mut Account<Bank>) -> Result<()> {
+    let position = user.position_mut(&bank.key())?;
+
+    if position.deposited_shares > 0 {
+        return Ok(());
+    }
+
+    bank.isolated_debt = bank.isolated_debt.saturating_sub(position.isolated_debt);
+    position.isolated_debt = 0;
+
+    Ok(())
+}
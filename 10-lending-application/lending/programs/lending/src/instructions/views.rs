@@ -0,0 +1,139 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+use crate::constants::BPS;
+use crate::error::ErrorCode;
+use crate::health::{
+    compute_health, compute_health_opening, load_priced_banks, upsert_priced_bank, Health,
+};
+use crate::interest::accrue_interest;
+use crate::math::{face_amount, mul_div, Rounding};
+use crate::price::{read_price, Price};
+use crate::state::{Bank, FallbackPrice, User};
+
+// View instructions never write accounts; clients call them through simulateTransaction
+// and read the Borsh-encoded result from the return data.
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct ObligationHealth {
+    pub borrowable_value: u128,
+    pub liquidation_value: u128,
+    pub debt_value: u128,
+    pub health_factor: u128,
+}
+
+pub fn process_get_obligation_health(ctx: Context<GetObligationHealth>) -> Result<ObligationHealth> {
+    let clock = Clock::get()?;
+
+    let priced_banks = load_priced_banks(ctx.remaining_accounts, &clock)?;
+    let health = compute_health(&ctx.accounts.user_account, &priced_banks)?;
+
+    Ok(ObligationHealth {
+        borrowable_value: health.borrowable_value,
+        liquidation_value: health.liquidation_value,
+        debt_value: health.debt_value,
+        health_factor: health.health_factor()?,
+    })
+}
+
+pub fn process_get_max_borrow(ctx: Context<GetPositionLimit>) -> Result<u64> {
+    let (bank, price, health) = simulate_health(&ctx, Some(&ctx.accounts.bank.key()))?;
+
+    let headroom = health.borrowable_value.saturating_sub(health.debt_value);
+    let mut max_borrow = price.amount_for(headroom, bank.mint_decimals)?;
+
+    max_borrow = max_borrow.min(bank.total_deposits.saturating_sub(bank.total_outstanding()));
+
+    if let Some(debt_ceiling_headroom) = health.debt_ceiling_headroom {
+        let ceiling_headroom = if bank.isolation.borrowable_in_isolation && health.isolated_banks.len() == 1 {
+            debt_ceiling_headroom
+        } else {
+            0
+        };
+
+        max_borrow = max_borrow.min(face_amount(ceiling_headroom, bank.mint_decimals)?);
+    }
+
+    if bank.borrow_cap > 0 {
+        max_borrow = max_borrow.min(bank.borrow_cap.saturating_sub(bank.total_outstanding()));
+    }
+
+    Ok(max_borrow)
+}
+
+pub fn process_get_max_withdraw(ctx: Context<GetPositionLimit>) -> Result<u64> {
+    let (bank, price, health) = simulate_health(&ctx, None)?;
+
+    let deposited_shares = ctx
+        .accounts
+        .user_account
+        .position(&ctx.accounts.bank.key())
+        .map_or(0, |position| position.deposited_shares);
+    let deposited = bank.deposit_shares_to_amount(deposited_shares)?;
+
+    let mut max_withdraw = deposited.min(bank.total_deposits.saturating_sub(bank.total_outstanding()));
+
+    if health.debt_value > 0 {
+        let (max_ltv, _) = bank.collateral_factors(health.emode_category);
+        let backs_borrowing = max_ltv > 0 && !(bank.isolation.isolated && health.has_debt_outside_isolation);
+
+        // Collateral that adds nothing to borrowing power can all go while the obligation is
+        // healthy, and none of it while it is not.
+        let health_limit = if backs_borrowing {
+            let headroom = health.borrowable_value.saturating_sub(health.debt_value);
+            let withdrawable_value = mul_div(headroom, BPS as u128, max_ltv as u128, Rounding::Down)?;
+
+            price.amount_for(withdrawable_value, bank.mint_decimals)?
+        } else if health.can_borrow() {
+            u64::MAX
+        } else {
+            0
+        };
+
+        max_withdraw = max_withdraw.min(health_limit);
+    }
+
+    Ok(max_withdraw)
+}
+
+fn simulate_health(
+    ctx: &Context<GetPositionLimit>,
+    opening: Option<&Pubkey>,
+) -> Result<(Bank, Price, Health)> {
+    let clock = Clock::get()?;
+
+    let mut bank = (*ctx.accounts.bank).clone();
+    accrue_interest(&mut bank, clock.unix_timestamp)?;
+
+    let price = read_price(
+        &ctx.accounts.price_update,
+        ctx.accounts.fallback_price.as_deref(),
+        &bank,
+        &ctx.accounts.bank.key(),
+        &clock,
+    )?;
+
+    let mut priced_banks = load_priced_banks(ctx.remaining_accounts, &clock)?;
+    upsert_priced_bank(&mut priced_banks, ctx.accounts.bank.key(), &bank, price);
+
+    let health = compute_health_opening(&ctx.accounts.user_account, &priced_banks, opening)?;
+
+    Ok((bank, price, health))
+}
+
+#[derive(Accounts)]
+pub struct GetObligationHealth<'info> {
+    pub user_account: Account<'info, User>,
+}
+
+#[derive(Accounts)]
+pub struct GetPositionLimit<'info> {
+    pub user_account: Account<'info, User>,
+
+    pub bank: Account<'info, Bank>,
+
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    #[account(address = bank.oracle.fallback_oracle @ ErrorCode::InvalidFallbackOracle)]
+    pub fallback_price: Option<Account<'info, FallbackPrice>>,
+}
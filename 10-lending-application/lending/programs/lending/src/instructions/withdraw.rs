@@ -1,47 +1,106 @@
-use std::f64::consts::E;
-
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
     token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
 };
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 
+use crate::constants::PAUSE_WITHDRAW;
 use crate::error::ErrorCode;
-use crate::state::{Bank, User};
+use crate::events::Withdrawn;
+use crate::instructions::shared::{
+    burn_receipts, payout_account, release_emptied_isolated_debt, unwrap_sol,
+};
+use crate::health::{compute_health, load_priced_banks, upsert_priced_bank};
+use crate::price::read_price;
+use crate::interest::accrue_bank_interest;
+use crate::math::Rounding;
+use crate::state::{Bank, FallbackPrice, User};
 
 pub fn process_withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+    let clock = Clock::get()?;
+
+    let bank = &mut ctx.accounts.bank;
+    bank.check_not_paused(PAUSE_WITHDRAW)?;
+    accrue_bank_interest(bank, clock.unix_timestamp)?;
+
     let user = &mut ctx.accounts.user_account;
-    let deposited_value;
+    let deposited_shares = user.position(&bank.key())?.deposited_shares;
 
-    if ctx.accounts.mint.to_account_info().key() == user.usdc_address {
-        deposited_value = user.deposited_usdc;
-    } else {
-        deposited_value = user.deposited_sol;
+    let mut shares_to_remove = bank.amount_to_deposit_shares(amount, Rounding::Up)?;
+
+    if shares_to_remove > deposited_shares {
+        return Err(ErrorCode::InsufficientFunds.into());
     }
 
-    let time_difference = Clock::get()?.unix_timestamp - user.last_updated;
+    // Shares left worth nothing, such as rounding dust or deposits written off by bad debt,
+    // go with the withdrawal so the position can close.
+    if bank.deposit_shares_to_amount(deposited_shares - shares_to_remove)? == 0 {
+        shares_to_remove = deposited_shares;
+    }
 
-    let bank = &mut ctx.accounts.bank;
-    bank.total_deposits = ((bank.total_deposits as f64)
-        * E.powf(bank.interest_rate as f64 / 100.0 * time_difference as f64))
-        as u64;
+    user.position_mut(&bank.key())?.deposited_shares -= shares_to_remove;
+    release_emptied_isolated_debt(user, bank)?;
 
-    let value_per_share = (bank.total_deposits as f64 / bank.total_deposited_shares as f64) as u64;
+    bank.total_deposited_shares -= shares_to_remove;
+    bank.update_totals()?;
 
-    let user_value = deposited_value as f64 / value_per_share as f64;
+    let health_factor = if user.positions.iter().any(|position| position.has_debt()) {
+        let price = read_price(
+            &ctx.accounts.price_update,
+            ctx.accounts.fallback_price.as_deref(),
+            bank,
+            &bank.key(),
+            &clock,
+        )?;
+        let mut priced_banks = load_priced_banks(ctx.remaining_accounts, &clock)?;
+        upsert_priced_bank(&mut priced_banks, bank.key(), bank, price);
 
-    if user_value < amount as f64 {
-        return Err(ErrorCode::InsufficientFunds.into());
-    }
+        let health = compute_health(user, &priced_banks)?;
+
+        if !health.can_borrow() {
+            return Err(ErrorCode::UnhealthyPosition.into());
+        }
+
+        health.health_factor()?
+    } else {
+        u128::MAX
+    };
+
+    user.close_empty_positions();
+
+    emit!(Withdrawn {
+        user: ctx.accounts.signer.key(),
+        bank: bank.key(),
+        amount,
+        shares: shares_to_remove,
+        supply_index: bank.supply_index,
+        health_factor,
+    });
+
+    burn_receipts(
+        &ctx.accounts.bank,
+        &ctx.accounts.receipt_mint,
+        &ctx.accounts.receipt_vault,
+        ctx.bumps.receipt_vault,
+        shares_to_remove,
+        &ctx.accounts.token_program,
+    )?;
+
+    let mint_key = ctx.accounts.mint.key();
+    let payout_account = payout_account(
+        &mint_key,
+        ctx.accounts.user_token_account.as_deref(),
+        ctx.accounts.unwrap_account.as_deref(),
+    )?;
 
     let transfer_cpi_accounts = TransferChecked {
         from: ctx.accounts.bank_token_account.to_account_info(),
-        to: ctx.accounts.user_token_account.to_account_info(),
+        to: payout_account.to_account_info(),
         mint: ctx.accounts.mint.to_account_info(),
-        authority: ctx.accounts.signer.to_account_info(),
+        authority: ctx.accounts.bank_token_account.to_account_info(),
     };
 
-    let mint_key = ctx.accounts.mint.key();
     let signer_seeds: &[&[&[u8]]] = &[&[
         b"treasury",
         mint_key.as_ref(),
@@ -54,20 +113,16 @@ pub fn process_withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
 
     transfer_checked(cpi_ctx, amount, decimals)?;
 
-    let shares_to_remove =
-        (amount as f64 / bank.total_deposits as f64) * bank.total_deposited_shares as f64;
-
-    if ctx.accounts.mint.to_account_info().key() == user.usdc_address {
-        user.deposited_usdc -= amount;
-        user.deposited_usdc_shares -= shares_to_remove as u64;
-    } else {
-        user.deposited_sol -= amount;
-        user.deposited_sol_shares -= shares_to_remove as u64;
+    if let Some(unwrap_account) = &ctx.accounts.unwrap_account {
+        unwrap_sol(
+            &ctx.accounts.bank,
+            ctx.bumps.bank,
+            unwrap_account,
+            &ctx.accounts.signer,
+            &ctx.accounts.token_program,
+        )?;
     }
 
-    bank.total_deposits -= amount;
-    bank.total_deposited_shares -= shares_to_remove as u64;
-
     Ok(())
 }
 
@@ -99,6 +154,19 @@ pub struct Withdraw<'info> {
     )]
     pub user_account: Account<'info, User>,
 
+    #[account(
+        mut,
+        address = bank.receipt_mint,
+    )]
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds=[b"receipt_vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub receipt_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
     #[account(
         init_if_needed,
         payer = signer,
@@ -106,7 +174,24 @@ pub struct Withdraw<'info> {
         associated_token::authority = signer,
         associated_token::token_program = token_program,
     )]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    // Temporary account native SOL is paid out through; pass it for the native mint only.
+    #[account(
+        init,
+        payer = signer,
+        seeds = [b"unwrap", signer.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = bank,
+        token::token_program = token_program,
+    )]
+    pub unwrap_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+    #[account(address = bank.oracle.fallback_oracle @ ErrorCode::InvalidFallbackOracle)]
+    pub fallback_price: Option<Account<'info, FallbackPrice>>,
 
     pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
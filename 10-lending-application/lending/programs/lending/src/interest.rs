@@ -0,0 +1,151 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    constants::{BPS, SECONDS_PER_YEAR, WAD},
+    error::ErrorCode,
+    events::InterestAccrued,
+    math::{mul_div, to_u64, Rounding},
+    state::{Bank, InterestRateModel},
+};
+
+fn share_of_deposits(bank: &Bank, amount: u64) -> Result<u128> {
+    if bank.total_deposits == 0 {
+        return Ok(0);
+    }
+
+    let share = mul_div(amount as u128, WAD, bank.total_deposits as u128, Rounding::Down)?;
+
+    Ok(share.min(WAD))
+}
+
+// Counts fixed-term principal, so fixed loans push the variable rate up like any other borrow.
+pub fn utilization(bank: &Bank) -> Result<u128> {
+    share_of_deposits(bank, bank.total_outstanding())
+}
+
+pub fn variable_utilization(bank: &Bank) -> Result<u128> {
+    share_of_deposits(bank, bank.total_borrowed)
+}
+
+pub fn borrow_rate(model: &InterestRateModel, utilization: u128) -> Result<u128> {
+    let bps_to_wad = |value: u64| mul_div(value as u128, WAD, BPS as u128, Rounding::Down);
+
+    let base_rate = bps_to_wad(model.base_rate)?;
+    let slope1 = bps_to_wad(model.slope1)?;
+    let slope2 = bps_to_wad(model.slope2)?;
+    let optimal_utilization = bps_to_wad(model.optimal_utilization)?;
+
+    let rate = if utilization <= optimal_utilization {
+        base_rate + mul_div(slope1, utilization, optimal_utilization, Rounding::Down)?
+    } else {
+        let excess_utilization = utilization - optimal_utilization;
+
+        base_rate
+            + slope1
+            + mul_div(
+                slope2,
+                excess_utilization,
+                WAD - optimal_utilization,
+                Rounding::Down,
+            )?
+    };
+
+    Ok(rate)
+}
+
+// Suppliers earn the variable rate only on the variable share of deposits; fixed-term
+// interest reaches them through `Bank::credit_fee` once it is paid.
+pub fn supply_rate(
+    model: &InterestRateModel,
+    utilization: u128,
+    variable_utilization: u128,
+) -> Result<u128> {
+    let borrow_rate = borrow_rate(model, utilization)?;
+    let gross_rate = mul_div(borrow_rate, variable_utilization, WAD, Rounding::Down)?;
+
+    mul_div(
+        gross_rate,
+        (BPS - model.reserve_factor) as u128,
+        BPS as u128,
+        Rounding::Down,
+    )
+}
+
+pub fn accrue_interest(bank: &mut Bank, now: i64) -> Result<()> {
+    let elapsed = now.saturating_sub(bank.last_updated);
+
+    if elapsed <= 0 {
+        return Ok(());
+    }
+
+    bank.last_updated = now;
+
+    if bank.total_borrowed_shares == 0 {
+        return Ok(());
+    }
+
+    let utilization = utilization(bank)?;
+
+    let borrow_growth = mul_div(
+        borrow_rate(&bank.interest_rate_model, utilization)?,
+        elapsed as u128,
+        SECONDS_PER_YEAR as u128,
+        Rounding::Down,
+    )?;
+    let supply_growth = mul_div(
+        supply_rate(&bank.interest_rate_model, utilization, variable_utilization(bank)?)?,
+        elapsed as u128,
+        SECONDS_PER_YEAR as u128,
+        Rounding::Down,
+    )?;
+
+    let interest = mul_div(bank.total_borrowed as u128, borrow_growth, WAD, Rounding::Down)?;
+    let reserves = mul_div(
+        interest,
+        bank.interest_rate_model.reserve_factor as u128,
+        BPS as u128,
+        Rounding::Down,
+    )?;
+
+    bank.protocol_reserves = bank
+        .protocol_reserves
+        .checked_add(to_u64(reserves)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    bank.borrow_index = mul_div(
+        bank.borrow_index,
+        WAD.checked_add(borrow_growth).ok_or(ErrorCode::MathOverflow)?,
+        WAD,
+        Rounding::Up,
+    )?;
+    bank.supply_index = mul_div(
+        bank.supply_index,
+        WAD.checked_add(supply_growth).ok_or(ErrorCode::MathOverflow)?,
+        WAD,
+        Rounding::Down,
+    )?;
+
+    bank.update_totals()
+}
+
+// Accrues a live bank account and logs the new indices. In-memory accrual for health
+// checks goes through `accrue_interest` directly and stays silent.
+pub fn accrue_bank_interest(bank: &mut Account<Bank>, now: i64) -> Result<()> {
+    let previous_borrow_index = bank.borrow_index;
+
+    accrue_interest(bank, now)?;
+
+    if bank.borrow_index != previous_borrow_index {
+        emit!(InterestAccrued {
+            bank: bank.key(),
+            supply_index: bank.supply_index,
+            borrow_index: bank.borrow_index,
+            total_deposits: bank.total_deposits,
+            total_borrowed: bank.total_borrowed,
+            protocol_reserves: bank.protocol_reserves,
+            timestamp: now,
+        });
+    }
+
+    Ok(())
+}
@@ -1,9 +1,21 @@
 use anchor_lang::prelude::*;
 use instructions::*;
 
-mod constants;
+pub use error::ErrorCode;
+pub use instructions::ObligationHealth;
+pub use state::*;
+
+pub mod constants;
 mod error;
+mod events;
+mod extensions;
+mod fixed_term;
+mod health;
 mod instructions;
+mod interest;
+mod math;
+mod price;
+mod shares;
 mod state;
 
 declare_id!("LKf9pkEij6jUa4GnsxUK2XU2XdQ1PfMFLUicfXgkA1e");
@@ -14,14 +26,84 @@ pub mod lending {
 
     pub fn init_bank(
         ctx: Context<InitBank>,
-        liquidation_threshold: u64,
-        max_ltv: u64,
+        risk_config: RiskConfig,
+        interest_rate_model: InterestRateModel,
+        oracle_config: OracleConfig,
+    ) -> Result<()> {
+        process_init_bank(ctx, risk_config, interest_rate_model, oracle_config)
+    }
+
+    pub fn update_bank_config(ctx: Context<UpdateBankConfig>, risk_config: RiskConfig) -> Result<()> {
+        process_update_bank_config(ctx, risk_config)
+    }
+
+    pub fn update_oracle_config(
+        ctx: Context<UpdateOracleConfig>,
+        oracle_config: OracleConfig,
+    ) -> Result<()> {
+        process_update_oracle_config(ctx, oracle_config)
+    }
+
+    pub fn update_isolation_config(
+        ctx: Context<UpdateIsolationConfig>,
+        isolation_config: IsolationConfig,
+    ) -> Result<()> {
+        process_update_isolation_config(ctx, isolation_config)
+    }
+
+    pub fn update_emode_config(ctx: Context<UpdateEModeConfig>, emode_config: EModeConfig) -> Result<()> {
+        process_update_emode_config(ctx, emode_config)
+    }
+
+    pub fn update_fixed_term_config(
+        ctx: Context<UpdateFixedTermConfig>,
+        fixed_term_config: FixedTermConfig,
+    ) -> Result<()> {
+        process_update_fixed_term_config(ctx, fixed_term_config)
+    }
+
+    pub fn update_caps(ctx: Context<UpdateCaps>, deposit_cap: u64, borrow_cap: u64) -> Result<()> {
+        process_update_caps(ctx, deposit_cap, borrow_cap)
+    }
+
+    pub fn update_flash_loan_fee(ctx: Context<UpdateFlashLoanFee>, flash_loan_fee: u64) -> Result<()> {
+        process_update_flash_loan_fee(ctx, flash_loan_fee)
+    }
+
+    pub fn update_fee_receiver(ctx: Context<UpdateFeeReceiver>, fee_receiver: Pubkey) -> Result<()> {
+        process_update_fee_receiver(ctx, fee_receiver)
+    }
+
+    pub fn update_fallback_price(
+        ctx: Context<UpdateFallbackPrice>,
+        price: i64,
+        exponent: i32,
+        conf: u64,
+    ) -> Result<()> {
+        process_update_fallback_price(ctx, price, exponent, conf)
+    }
+
+    pub fn update_interest_rate_model(
+        ctx: Context<UpdateInterestRateModel>,
+        interest_rate_model: InterestRateModel,
     ) -> Result<()> {
-        process_init_bank(ctx, liquidation_threshold, max_ltv)
+        process_update_interest_rate_model(ctx, interest_rate_model)
+    }
+
+    pub fn update_pause_guardian(ctx: Context<UpdatePauseGuardian>, pause_guardian: Pubkey) -> Result<()> {
+        process_update_pause_guardian(ctx, pause_guardian)
+    }
+
+    pub fn set_paused(ctx: Context<SetPaused>, paused: u8) -> Result<()> {
+        process_set_paused(ctx, paused)
+    }
+
+    pub fn pause(ctx: Context<Pause>, operations: u8) -> Result<()> {
+        process_pause(ctx, operations)
     }
 
-    pub fn init_user(ctx: Context<InitUser>, usdc_address: Pubkey) -> Result<()> {
-        process_init_user(ctx, usdc_address)
+    pub fn init_user(ctx: Context<InitUser>) -> Result<()> {
+        process_init_user(ctx)
     }
 
     pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
@@ -32,15 +114,79 @@ pub mod lending {
         process_withdraw(ctx, amount)
     }
 
+    pub fn deposit_collateral(ctx: Context<DepositCollateral>, shares: u64) -> Result<()> {
+        process_deposit_collateral(ctx, shares)
+    }
+
+    pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, shares: u64) -> Result<()> {
+        process_withdraw_collateral(ctx, shares)
+    }
+
     pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
         process_borrow(ctx, amount)
     }
 
+    pub fn borrow_fixed(ctx: Context<Borrow>, amount: u64, term: i64) -> Result<()> {
+        process_borrow_fixed(ctx, amount, term)
+    }
+
+    pub fn approve_borrow_delegate(ctx: Context<ApproveBorrowDelegate>, amount: u64) -> Result<()> {
+        process_approve_borrow_delegate(ctx, amount)
+    }
+
+    pub fn revoke_borrow_delegate(ctx: Context<RevokeBorrowDelegate>) -> Result<()> {
+        process_revoke_borrow_delegate(ctx)
+    }
+
     pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
         process_repay(ctx, amount)
     }
 
-    pub fn liquidate(ctx: Context<Liquidate>) -> Result<()> {
-        process_liquidate(ctx)
+    pub fn repay_fixed(ctx: Context<Repay>) -> Result<()> {
+        process_repay_fixed(ctx)
+    }
+
+    pub fn liquidate(ctx: Context<Liquidate>, repay_amount: u64) -> Result<()> {
+        process_liquidate(ctx, repay_amount)
+    }
+
+    pub fn collect_protocol_fees(ctx: Context<CollectProtocolFees>) -> Result<()> {
+        process_collect_protocol_fees(ctx)
+    }
+
+    pub fn fund_insurance(ctx: Context<FundInsurance>, amount: u64) -> Result<()> {
+        process_fund_insurance(ctx, amount)
+    }
+
+    pub fn settle_bad_debt(ctx: Context<SettleBadDebt>) -> Result<()> {
+        process_settle_bad_debt(ctx)
+    }
+
+    pub fn flash_borrow(ctx: Context<FlashBorrow>, amount: u64) -> Result<()> {
+        process_flash_borrow(ctx, amount)
+    }
+
+    pub fn flash_repay(ctx: Context<FlashRepay>) -> Result<()> {
+        process_flash_repay(ctx)
+    }
+
+    pub fn close_user(ctx: Context<CloseUser>) -> Result<()> {
+        process_close_user(ctx)
+    }
+
+    pub fn close_bank(ctx: Context<CloseBank>) -> Result<()> {
+        process_close_bank(ctx)
+    }
+
+    pub fn get_obligation_health(ctx: Context<GetObligationHealth>) -> Result<ObligationHealth> {
+        process_get_obligation_health(ctx)
+    }
+
+    pub fn get_max_borrow(ctx: Context<GetPositionLimit>) -> Result<u64> {
+        process_get_max_borrow(ctx)
+    }
+
+    pub fn get_max_withdraw(ctx: Context<GetPositionLimit>) -> Result<u64> {
+        process_get_max_withdraw(ctx)
     }
 }
@@ -0,0 +1,41 @@
+use anchor_lang::prelude::*;
+
+use crate::constants::WAD;
+use crate::error::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+pub fn mul_div(a: u128, b: u128, c: u128, rounding: Rounding) -> Result<u128> {
+    let product = a.checked_mul(b).ok_or(ErrorCode::MathOverflow)?;
+    let quotient = product.checked_div(c).ok_or(ErrorCode::MathOverflow)?;
+
+    if rounding == Rounding::Up && product % c != 0 {
+        return quotient.checked_add(1).ok_or(ErrorCode::MathOverflow.into());
+    }
+
+    Ok(quotient)
+}
+
+// `amount` base units of a token with `decimals` decimals, at face value scaled by WAD.
+pub fn face_value(amount: u64, decimals: u8, rounding: Rounding) -> Result<u128> {
+    mul_div(amount as u128, WAD, pow10(decimals)?, rounding)
+}
+
+// Base units worth `value` at face value, rounded down.
+pub fn face_amount(value: u128, decimals: u8) -> Result<u64> {
+    to_u64(mul_div(value, pow10(decimals)?, WAD, Rounding::Down)?)
+}
+
+fn pow10(decimals: u8) -> Result<u128> {
+    10u128
+        .checked_pow(decimals as u32)
+        .ok_or(ErrorCode::MathOverflow.into())
+}
+
+pub fn to_u64(value: u128) -> Result<u64> {
+    u64::try_from(value).map_err(|_| ErrorCode::MathOverflow.into())
+}
@@ -0,0 +1,167 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::{error::GetPriceError, price_update::PriceUpdateV2};
+
+use crate::{
+    constants::BPS,
+    error::ErrorCode,
+    math::{mul_div, to_u64, Rounding},
+    state::{Bank, FallbackPrice, OracleConfig},
+};
+
+const WAD_DECIMALS: i32 = 18;
+
+// USD price of one whole token, scaled by WAD.
+#[derive(Clone, Copy)]
+pub struct Price {
+    pub value: u128,
+}
+
+impl Price {
+    pub fn from_oracle(price: i64, exponent: i32) -> Result<Self> {
+        if price <= 0 {
+            return Err(ErrorCode::InvalidPrice.into());
+        }
+
+        let scale = WAD_DECIMALS
+            .checked_add(exponent)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let factor = pow10(scale.unsigned_abs())?;
+
+        let value = if scale >= 0 {
+            (price as u128)
+                .checked_mul(factor)
+                .ok_or(ErrorCode::MathOverflow)?
+        } else {
+            (price as u128) / factor
+        };
+
+        if value == 0 {
+            return Err(ErrorCode::InvalidPrice.into());
+        }
+
+        Ok(Self { value })
+    }
+
+    // USD value of `amount` base units, scaled by WAD.
+    pub fn value_of(&self, amount: u64, decimals: u8) -> Result<u128> {
+        let unit = pow10(decimals as u32)?;
+        let whole = (amount as u128) / unit;
+        let fraction = (amount as u128) % unit;
+
+        whole
+            .checked_mul(self.value)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_add(mul_div(fraction, self.value, unit, Rounding::Down)?)
+            .ok_or(ErrorCode::MathOverflow.into())
+    }
+
+    // Base units worth `value` (WAD-scaled USD), rounded down.
+    pub fn amount_for(&self, value: u128, decimals: u8) -> Result<u64> {
+        let whole = value / self.value;
+        let remainder = value % self.value;
+        let unit = pow10(decimals as u32)?;
+
+        let amount = whole
+            .checked_mul(unit)
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_add(mul_div(remainder, unit, self.value, Rounding::Down)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        to_u64(amount)
+    }
+}
+
+fn pow10(exponent: u32) -> Result<u128> {
+    10u128
+        .checked_pow(exponent)
+        .ok_or(ErrorCode::MathOverflow.into())
+}
+
+fn check_confidence(price: i64, conf: u64, oracle: &OracleConfig) -> Result<()> {
+    let max_confidence = mul_div(
+        price.unsigned_abs() as u128,
+        oracle.max_confidence as u128,
+        BPS as u128,
+        Rounding::Down,
+    )?;
+
+    if conf as u128 > max_confidence {
+        return Err(ErrorCode::PriceConfidenceTooWide.into());
+    }
+
+    Ok(())
+}
+
+fn read_primary(price_update: &PriceUpdateV2, oracle: &OracleConfig, clock: &Clock) -> Result<Price> {
+    let price = price_update.get_price_no_older_than(clock, oracle.max_age, &oracle.feed_id)?;
+
+    let normalized = Price::from_oracle(price.price, price.exponent)?;
+    check_confidence(price.price, price.conf, oracle)?;
+
+    Ok(normalized)
+}
+
+fn read_fallback(fallback: &FallbackPrice, oracle: &OracleConfig, clock: &Clock) -> Result<Price> {
+    let age = clock.unix_timestamp.saturating_sub(fallback.publish_time);
+
+    if age < 0 || age as u64 > oracle.max_age {
+        return Err(ErrorCode::StalePrice.into());
+    }
+
+    let normalized = Price::from_oracle(fallback.price, fallback.exponent)?;
+    check_confidence(fallback.price, fallback.conf, oracle)?;
+
+    Ok(normalized)
+}
+
+fn check_deviation(primary: &Price, fallback: &Price, oracle: &OracleConfig) -> Result<()> {
+    let difference = primary.value.abs_diff(fallback.value);
+    let deviation = mul_div(
+        difference,
+        BPS as u128,
+        primary.value.min(fallback.value),
+        Rounding::Up,
+    )?;
+
+    if deviation > oracle.max_deviation as u128 {
+        return Err(ErrorCode::PriceDeviationTooHigh.into());
+    }
+
+    Ok(())
+}
+
+// The fallback is only used while the primary feed is stale. Any other primary failure, such
+// as a wrong feed or a wide confidence interval, fails closed.
+pub fn read_price(
+    price_update: &PriceUpdateV2,
+    fallback: Option<&FallbackPrice>,
+    bank: &Bank,
+    bank_key: &Pubkey,
+    clock: &Clock,
+) -> Result<Price> {
+    let oracle = &bank.oracle;
+
+    if !oracle.has_fallback() {
+        return read_primary(price_update, oracle, clock);
+    }
+
+    let fallback = fallback.ok_or(ErrorCode::InvalidFallbackOracle)?;
+
+    if fallback.bank != *bank_key {
+        return Err(ErrorCode::InvalidFallbackOracle.into());
+    }
+
+    match read_primary(price_update, oracle, clock) {
+        Ok(primary) => {
+            if let Ok(secondary) = read_fallback(fallback, oracle, clock) {
+                check_deviation(&primary, &secondary, oracle)?;
+            }
+
+            Ok(primary)
+        }
+        Err(error) if error == GetPriceError::PriceTooOld.into() => {
+            read_fallback(fallback, oracle, clock)
+        }
+        Err(error) => Err(error),
+    }
+}
@@ -0,0 +1,263 @@
+use anchor_lang::prelude::*;
+
+use crate::constants::WAD;
+use crate::math::{mul_div, to_u64, Rounding};
+
+// Share <-> amount conversions against a WAD-scaled index. Callers pick the rounding
+// direction so that every conversion favors the protocol.
+
+pub fn shares_to_amount(shares: u64, index: u128, rounding: Rounding) -> Result<u64> {
+    to_u64(mul_div(shares as u128, index, WAD, rounding)?)
+}
+
+pub fn amount_to_shares(amount: u64, index: u128, rounding: Rounding) -> Result<u64> {
+    to_u64(mul_div(amount as u128, WAD, index, rounding)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::constants::BPS;
+    use crate::interest::accrue_interest;
+    use crate::state::{Bank, InterestRateModel};
+
+    const USERS: usize = 4;
+    const THIRTY_DAYS: i64 = 30 * 86_400;
+
+    fn index() -> impl Strategy<Value = u128> {
+        WAD..=10 * WAD
+    }
+
+    fn amount() -> impl Strategy<Value = u64> {
+        1..=1_000_000_000_000_000u64
+    }
+
+    #[derive(Clone, Debug)]
+    enum Operation {
+        Deposit { user: usize, amount: u64 },
+        Withdraw { user: usize, amount: u64 },
+        WithdrawAll { user: usize },
+        Borrow { user: usize, amount: u64 },
+        Repay { user: usize, amount: u64 },
+        Accrue { elapsed: i64 },
+    }
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        prop_oneof![
+            (0..USERS, amount()).prop_map(|(user, amount)| Operation::Deposit { user, amount }),
+            (0..USERS, amount()).prop_map(|(user, amount)| Operation::Withdraw { user, amount }),
+            (0..USERS).prop_map(|user| Operation::WithdrawAll { user }),
+            (0..USERS, amount()).prop_map(|(user, amount)| Operation::Borrow { user, amount }),
+            (0..USERS, amount()).prop_map(|(user, amount)| Operation::Repay { user, amount }),
+            (0..=THIRTY_DAYS).prop_map(|elapsed| Operation::Accrue { elapsed }),
+        ]
+    }
+
+    // A real bank driven the way the instructions drive it, with the users' shares and the
+    // treasury balance tracked alongside. Operations the program would reject, or that the
+    // treasury could not pay out, are skipped.
+    struct Market {
+        bank: Bank,
+        now: i64,
+        deposited_shares: [u64; USERS],
+        borrowed_shares: [u64; USERS],
+        vault: u64,
+    }
+
+    impl Market {
+        fn new(supply_index: u128, borrow_index: u128, reserve_factor: u64) -> Self {
+            let mut bank = Bank::deserialize(&mut &[0; Bank::INIT_SPACE][..]).unwrap();
+
+            bank.supply_index = supply_index;
+            bank.borrow_index = borrow_index;
+            bank.interest_rate_model = InterestRateModel {
+                base_rate: 200,
+                slope1: 400,
+                optimal_utilization: 8_000,
+                slope2: 7_500,
+                reserve_factor,
+            };
+
+            Self {
+                bank,
+                now: 0,
+                deposited_shares: [0; USERS],
+                borrowed_shares: [0; USERS],
+                vault: 0,
+            }
+        }
+
+        fn apply(&mut self, operation: &Operation) {
+            let bank = &mut self.bank;
+
+            match *operation {
+                Operation::Deposit { user, amount } => {
+                    let shares = bank.amount_to_deposit_shares(amount, Rounding::Down).unwrap();
+
+                    if shares == 0 {
+                        return;
+                    }
+
+                    self.deposited_shares[user] += shares;
+                    bank.total_deposited_shares += shares;
+                    bank.update_totals().unwrap();
+                    self.vault += amount;
+                }
+                Operation::Withdraw { user, amount } => self.withdraw(user, amount),
+                Operation::WithdrawAll { user } => {
+                    let amount = bank.deposit_shares_to_amount(self.deposited_shares[user]).unwrap();
+                    self.withdraw(user, amount);
+                }
+                Operation::Borrow { user, amount } => {
+                    if amount > self.vault {
+                        return;
+                    }
+
+                    let shares = bank.amount_to_borrow_shares(amount, Rounding::Up).unwrap();
+
+                    self.borrowed_shares[user] += shares;
+                    bank.total_borrowed_shares += shares;
+                    bank.update_totals().unwrap();
+                    self.vault -= amount;
+                }
+                Operation::Repay { user, amount } => {
+                    let debt = bank.borrow_shares_to_amount(self.borrowed_shares[user]).unwrap();
+                    let amount = amount.min(debt);
+
+                    let shares = if amount == debt {
+                        self.borrowed_shares[user]
+                    } else {
+                        bank.amount_to_borrow_shares(amount, Rounding::Down).unwrap()
+                    };
+
+                    self.borrowed_shares[user] -= shares;
+                    bank.total_borrowed_shares -= shares;
+                    bank.update_totals().unwrap();
+                    self.vault += amount;
+                }
+                Operation::Accrue { elapsed } => {
+                    self.now += elapsed;
+                    accrue_interest(bank, self.now).unwrap();
+                }
+            }
+        }
+
+        fn withdraw(&mut self, user: usize, amount: u64) {
+            let bank = &mut self.bank;
+            let shares = bank.amount_to_deposit_shares(amount, Rounding::Up).unwrap();
+
+            if shares > self.deposited_shares[user] || amount > self.vault {
+                return;
+            }
+
+            self.deposited_shares[user] -= shares;
+            bank.total_deposited_shares -= shares;
+            bank.update_totals().unwrap();
+            self.vault -= amount;
+        }
+
+        fn redeemable(&self) -> u64 {
+            self.deposited_shares
+                .iter()
+                .map(|shares| self.bank.deposit_shares_to_amount(*shares).unwrap())
+                .sum()
+        }
+
+        fn owed(&self) -> u64 {
+            self.borrowed_shares
+                .iter()
+                .map(|shares| self.bank.borrow_shares_to_amount(*shares).unwrap())
+                .sum()
+        }
+
+        // Checks the bank's own bookkeeping against the users it serves.
+        fn check(&self) -> std::result::Result<(), TestCaseError> {
+            let bank = &self.bank;
+
+            prop_assert_eq!(self.deposited_shares.iter().sum::<u64>(), bank.total_deposited_shares);
+            prop_assert_eq!(self.borrowed_shares.iter().sum::<u64>(), bank.total_borrowed_shares);
+
+            // Per-user amounts round against the user, so they never beat the bank's totals.
+            prop_assert!(self.redeemable() <= bank.total_deposits);
+            prop_assert!(self.owed() >= bank.total_borrowed);
+
+            // Everything suppliers and the protocol can claim is backed by the treasury plus
+            // what borrowers owe.
+            prop_assert!(
+                bank.total_deposits as u128 + bank.protocol_reserves as u128
+                    <= self.vault as u128 + self.owed() as u128
+            );
+
+            Ok(())
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn deposit_then_redeem_never_returns_more(amount in amount(), index in index()) {
+            let shares = amount_to_shares(amount, index, Rounding::Down).unwrap();
+            let redeemed = shares_to_amount(shares, index, Rounding::Down).unwrap();
+
+            prop_assert!(redeemed <= amount);
+        }
+
+        #[test]
+        fn withdraw_burns_at_least_its_value(amount in amount(), index in index()) {
+            let shares = amount_to_shares(amount, index, Rounding::Up).unwrap();
+
+            prop_assert!(shares_to_amount(shares, index, Rounding::Up).unwrap() >= amount);
+        }
+
+        #[test]
+        fn full_balance_is_always_withdrawable(shares in 0..=1_000_000_000_000_000u64, index in index()) {
+            let amount = shares_to_amount(shares, index, Rounding::Down).unwrap();
+
+            prop_assert!(amount_to_shares(amount, index, Rounding::Up).unwrap() <= shares);
+        }
+
+        #[test]
+        fn borrowed_debt_is_never_understated(amount in amount(), index in index()) {
+            let shares = amount_to_shares(amount, index, Rounding::Up).unwrap();
+
+            prop_assert!(shares_to_amount(shares, index, Rounding::Up).unwrap() >= amount);
+        }
+
+        #[test]
+        fn repay_never_clears_more_than_paid(amount in amount(), index in index()) {
+            let shares = amount_to_shares(amount, index, Rounding::Down).unwrap();
+
+            prop_assert!(shares_to_amount(shares, index, Rounding::Down).unwrap() <= amount);
+        }
+
+        #[test]
+        fn operations_never_create_value(
+            supply_index in index(),
+            borrow_index in index(),
+            reserve_factor in 0..=BPS,
+            operations in prop::collection::vec(operation(), 1..64),
+        ) {
+            let mut market = Market::new(supply_index, borrow_index, reserve_factor);
+
+            for operation in &operations {
+                market.apply(operation);
+                market.check()?;
+            }
+
+            for user in 0..USERS {
+                market.apply(&Operation::Repay { user, amount: u64::MAX });
+            }
+
+            market.check()?;
+            prop_assert_eq!(market.bank.total_borrowed_shares, 0);
+
+            for user in 0..USERS {
+                market.apply(&Operation::WithdrawAll { user });
+            }
+
+            market.check()?;
+            prop_assert!(market.bank.protocol_reserves <= market.vault);
+        }
+    }
+}
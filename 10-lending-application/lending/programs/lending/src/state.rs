@@ -1,24 +1,117 @@
 use anchor_lang::prelude::*;
 
+use crate::math::{mul_div, to_u64, Rounding};
+use crate::constants::{BPS, MAX_BORROW_RATE, MAX_LATE_PENALTY_RATE, MAX_LIQUIDATION_BONUS, MAX_POSITIONS, WAD};
+use crate::error::ErrorCode;
+use crate::shares::{amount_to_shares, shares_to_amount};
+
 #[account]
 #[derive(InitSpace)]
 pub struct User {
     pub owner: Pubkey,
 
-    pub deposited_sol: u64,
-    pub deposited_sol_shares: u64,
-    pub borrowed_sol: u64,
-    pub borrowed_sol_shares: u64,
+    pub positions: [Position; MAX_POSITIONS],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct Position {
+    pub bank: Pubkey,
 
-    pub deposited_usdc: u64,
-    pub deposited_usdc_shares: u64,
-    pub borrowed_usdc: u64,
-    pub borrowed_usdc_shares: u64,
+    pub deposited_shares: u64,
+    pub borrowed_shares: u64,
 
-    pub usdc_address: Pubkey,
+    pub fixed_borrow: FixedBorrow,
 
-    pub last_updated: i64,
-    pub last_updated_borrow: i64,
+    // On an isolated collateral position, the part of the bank's `isolated_debt` this
+    // obligation accounts for.
+    pub isolated_debt: u128,
+}
+
+impl Position {
+    pub fn is_empty(&self) -> bool {
+        self.deposited_shares == 0 && !self.has_debt()
+    }
+
+    pub fn has_debt(&self) -> bool {
+        self.borrowed_shares > 0 || self.fixed_borrow.principal > 0
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct FixedBorrow {
+    pub principal: u64,
+    // Interest and late penalties accrued up to `last_accrued` but not yet paid.
+    pub accrued: u64,
+    // Annual rate in basis points, locked at origination.
+    pub rate: u64,
+
+    pub last_accrued: i64,
+    pub maturity: i64,
+
+    // The bank's penalty terms when the loan was opened.
+    pub early_repay_penalty: u64,
+    pub late_penalty_rate: u64,
+}
+
+impl FixedBorrow {
+    pub fn is_matured(&self, now: i64) -> bool {
+        self.principal > 0 && now >= self.maturity
+    }
+}
+
+impl User {
+    pub fn position(&self, bank: &Pubkey) -> Result<&Position> {
+        self.positions
+            .iter()
+            .find(|position| position.bank == *bank)
+            .ok_or(ErrorCode::PositionNotFound.into())
+    }
+
+    pub fn position_mut(&mut self, bank: &Pubkey) -> Result<&mut Position> {
+        self.positions
+            .iter_mut()
+            .find(|position| position.bank == *bank)
+            .ok_or(ErrorCode::PositionNotFound.into())
+    }
+
+    pub fn position_or_open(&mut self, bank: &Pubkey) -> Result<&mut Position> {
+        let index = self
+            .positions
+            .iter()
+            .position(|position| position.bank == *bank)
+            .or_else(|| {
+                self.positions
+                    .iter()
+                    .position(|position| position.bank == Pubkey::default())
+            })
+            .ok_or(ErrorCode::PositionLimitReached)?;
+
+        let position = &mut self.positions[index];
+        position.bank = *bank;
+
+        Ok(position)
+    }
+
+    pub fn close_empty_positions(&mut self) {
+        for position in self.positions.iter_mut() {
+            if position.is_empty() {
+                *position = Position::default();
+            }
+        }
+    }
+}
+
+// Lets `delegate` borrow from `bank` against the owner's collateral, with the debt recorded
+// on the owner's position.
+#[account]
+#[derive(InitSpace)]
+pub struct BorrowAllowance {
+    pub owner: Pubkey,
+    pub bank: Pubkey,
+    pub delegate: Pubkey,
+
+    // Remaining amount, in the bank's token, the delegate may still borrow.
+    pub amount: u64,
 }
 
 #[account]
@@ -27,6 +120,9 @@ pub struct Bank {
     pub authority: Pubkey,
 
     pub mint_address: Pubkey,
+    pub mint_decimals: u8,
+
+    pub receipt_mint: Pubkey,
 
     pub total_deposits: u64,
     pub total_deposited_shares: u64,
@@ -34,6 +130,9 @@ pub struct Bank {
     pub total_borrowed: u64,
     pub total_borrowed_shares: u64,
 
+    pub supply_index: u128,
+    pub borrow_index: u128,
+
     pub liquidation_threshold: u64,
     pub liquidation_bonus: u64,
     pub liquidation_close_factor: u64,
@@ -42,5 +141,373 @@ pub struct Bank {
 
     pub last_updated: i64,
 
-    pub interest_rate: u64,
+    pub interest_rate_model: InterestRateModel,
+
+    pub oracle: OracleConfig,
+
+    pub protocol_reserves: u64,
+    pub fee_receiver: Pubkey,
+
+    pub deposit_cap: u64,
+    pub borrow_cap: u64,
+
+    pub flash_loan_fee: u64,
+    pub flash_loan_amount: u64,
+
+    pub pause_guardian: Pubkey,
+    pub paused: u8,
+
+    pub isolation: IsolationConfig,
+    pub emode: EModeConfig,
+
+    pub fixed_term: FixedTermConfig,
+    pub total_fixed_borrowed: u64,
+
+    // Debt borrowed against this bank's collateral while isolated, at face value scaled by WAD.
+    pub isolated_debt: u128,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct RiskConfig {
+    pub max_ltv: u64,
+    pub liquidation_threshold: u64,
+    pub liquidation_bonus: u64,
+    pub liquidation_close_factor: u64,
+}
+
+impl RiskConfig {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.max_ltv > 0 && self.max_ltv < self.liquidation_threshold,
+            ErrorCode::InvalidRiskConfig
+        );
+        require!(self.liquidation_threshold <= BPS, ErrorCode::InvalidRiskConfig);
+        require!(
+            self.liquidation_bonus <= MAX_LIQUIDATION_BONUS,
+            ErrorCode::InvalidRiskConfig
+        );
+        // A liquidated position must still hold enough collateral to pay out the bonus.
+        require!(
+            self.liquidation_threshold as u128 * (BPS + self.liquidation_bonus) as u128
+                <= (BPS as u128) * (BPS as u128),
+            ErrorCode::InvalidRiskConfig
+        );
+        require!(
+            self.liquidation_close_factor > 0 && self.liquidation_close_factor <= BPS,
+            ErrorCode::InvalidRiskConfig
+        );
+
+        Ok(())
+    }
+}
+
+// Collateral in an isolated bank can only back debt in banks that are borrowable in
+// isolation, and only while the obligation's total debt value stays under the ceiling.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct IsolationConfig {
+    pub isolated: bool,
+    // Cap on `Bank::isolated_debt`, across all obligations. Banks borrowable in isolation are
+    // stablecoins, so debt counts at face value as WAD-scaled USD.
+    pub debt_ceiling: u128,
+
+    pub borrowable_in_isolation: bool,
+}
+
+impl IsolationConfig {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            !self.isolated || self.debt_ceiling > 0,
+            ErrorCode::InvalidIsolationConfig
+        );
+        require!(
+            !(self.isolated && self.borrowable_in_isolation),
+            ErrorCode::InvalidIsolationConfig
+        );
+
+        Ok(())
+    }
+}
+
+// Obligations whose positions all sit in the same non-zero category use the e-mode
+// max LTV and liquidation threshold instead of the bank's own.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct EModeConfig {
+    pub category: u8,
+    pub max_ltv: u64,
+    pub liquidation_threshold: u64,
+}
+
+impl EModeConfig {
+    pub fn validate(&self, risk_config: &RiskConfig) -> Result<()> {
+        if self.category == 0 {
+            require!(
+                self.max_ltv == 0 && self.liquidation_threshold == 0,
+                ErrorCode::InvalidEModeConfig
+            );
+
+            return Ok(());
+        }
+
+        RiskConfig {
+            max_ltv: self.max_ltv,
+            liquidation_threshold: self.liquidation_threshold,
+            ..*risk_config
+        }
+        .validate()
+        .map_err(|_| ErrorCode::InvalidEModeConfig)?;
+
+        require!(
+            self.max_ltv >= risk_config.max_ltv
+                && self.liquidation_threshold >= risk_config.liquidation_threshold,
+            ErrorCode::InvalidEModeConfig
+        );
+
+        Ok(())
+    }
+}
+
+// Fixed-term loans are disabled while `max_term` is zero. Rates are annual, in basis points.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct FixedTermConfig {
+    pub max_term: i64,
+    // Added to the variable borrow rate at origination.
+    pub rate_premium: u64,
+    // Charged on outstanding principal when a loan is repaid before maturity.
+    pub early_repay_penalty: u64,
+    // Accrues on principal instead of the locked rate once a loan is past maturity.
+    pub late_penalty_rate: u64,
+}
+
+impl FixedTermConfig {
+    pub fn validate(&self) -> Result<()> {
+        require!(self.max_term >= 0, ErrorCode::InvalidFixedTermConfig);
+        require!(
+            self.early_repay_penalty <= BPS,
+            ErrorCode::InvalidFixedTermConfig
+        );
+        require!(
+            self.late_penalty_rate <= MAX_LATE_PENALTY_RATE,
+            ErrorCode::InvalidFixedTermConfig
+        );
+
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct FallbackPrice {
+    pub bank: Pubkey,
+
+    pub price: i64,
+    pub exponent: i32,
+    pub conf: u64,
+
+    pub publish_time: i64,
+}
+
+impl FallbackPrice {
+    pub fn address(bank: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[b"fallback_price", bank.as_ref()], &crate::ID).0
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct OracleConfig {
+    pub feed_id: [u8; 32],
+    pub max_age: u64,
+    pub max_confidence: u64,
+
+    pub fallback_oracle: Pubkey,
+    pub max_deviation: u64,
+}
+
+impl OracleConfig {
+    pub fn has_fallback(&self) -> bool {
+        self.fallback_oracle != Pubkey::default()
+    }
+
+    // The fallback must be the bank's own `update_fallback_price` account.
+    pub fn validate(&self, bank: &Pubkey) -> Result<()> {
+        require!(self.feed_id != [0; 32], ErrorCode::InvalidOracleConfig);
+        require!(self.max_age > 0, ErrorCode::InvalidOracleConfig);
+        require!(
+            self.max_confidence > 0 && self.max_confidence <= BPS,
+            ErrorCode::InvalidOracleConfig
+        );
+        require!(
+            !self.has_fallback() || (self.max_deviation > 0 && self.max_deviation <= BPS),
+            ErrorCode::InvalidOracleConfig
+        );
+        require!(
+            !self.has_fallback() || self.fallback_oracle == FallbackPrice::address(bank),
+            ErrorCode::InvalidOracleConfig
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct InterestRateModel {
+    pub base_rate: u64,
+    pub slope1: u64,
+    pub optimal_utilization: u64,
+    pub slope2: u64,
+    pub reserve_factor: u64,
+}
+
+impl InterestRateModel {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.optimal_utilization > 0 && self.optimal_utilization < BPS,
+            ErrorCode::InvalidInterestRateModel
+        );
+        require!(self.reserve_factor <= BPS, ErrorCode::InvalidInterestRateModel);
+
+        // A rate large enough to overflow accrual would brick the bank, including this update.
+        let max_rate = self
+            .base_rate
+            .checked_add(self.slope1)
+            .and_then(|rate| rate.checked_add(self.slope2));
+        require!(
+            max_rate.is_some_and(|rate| rate <= MAX_BORROW_RATE),
+            ErrorCode::InvalidInterestRateModel
+        );
+
+        Ok(())
+    }
+}
+
+impl Bank {
+    pub fn risk_config(&self) -> RiskConfig {
+        RiskConfig {
+            max_ltv: self.max_ltv,
+            liquidation_threshold: self.liquidation_threshold,
+            liquidation_bonus: self.liquidation_bonus,
+            liquidation_close_factor: self.liquidation_close_factor,
+        }
+    }
+
+    pub fn set_risk_config(&mut self, config: RiskConfig) {
+        self.max_ltv = config.max_ltv;
+        self.liquidation_threshold = config.liquidation_threshold;
+        self.liquidation_bonus = config.liquidation_bonus;
+        self.liquidation_close_factor = config.liquidation_close_factor;
+    }
+
+    // Max LTV and liquidation threshold for an obligation in `emode_category`.
+    pub fn collateral_factors(&self, emode_category: u8) -> (u64, u64) {
+        if emode_category != 0 && emode_category == self.emode.category {
+            (self.emode.max_ltv, self.emode.liquidation_threshold)
+        } else {
+            (self.max_ltv, self.liquidation_threshold)
+        }
+    }
+
+    pub fn deposit_shares_to_amount(&self, shares: u64) -> Result<u64> {
+        shares_to_amount(shares, self.supply_index, Rounding::Down)
+    }
+
+    pub fn amount_to_deposit_shares(&self, amount: u64, rounding: Rounding) -> Result<u64> {
+        amount_to_shares(amount, self.supply_index, rounding)
+    }
+
+    pub fn borrow_shares_to_amount(&self, shares: u64) -> Result<u64> {
+        shares_to_amount(shares, self.borrow_index, Rounding::Up)
+    }
+
+    pub fn amount_to_borrow_shares(&self, amount: u64, rounding: Rounding) -> Result<u64> {
+        amount_to_shares(amount, self.borrow_index, rounding)
+    }
+
+    pub fn check_not_paused(&self, operation: u8) -> Result<()> {
+        if self.paused & operation != 0 {
+            return Err(ErrorCode::MarketPaused.into());
+        }
+
+        Ok(())
+    }
+
+    pub fn check_deposit_cap(&self) -> Result<()> {
+        if self.deposit_cap > 0 && self.total_deposits > self.deposit_cap {
+            return Err(ErrorCode::DepositCapExceeded.into());
+        }
+
+        Ok(())
+    }
+
+    // Variable debt plus fixed-term principal.
+    pub fn total_outstanding(&self) -> u64 {
+        self.total_borrowed.saturating_add(self.total_fixed_borrowed)
+    }
+
+    pub fn check_borrow_cap(&self) -> Result<()> {
+        if self.borrow_cap > 0 && self.total_outstanding() > self.borrow_cap {
+            return Err(ErrorCode::BorrowCapExceeded.into());
+        }
+
+        Ok(())
+    }
+
+    pub fn credit_fee(&mut self, fee: u64) -> Result<()> {
+        let reserves = to_u64(mul_div(
+            fee as u128,
+            self.interest_rate_model.reserve_factor as u128,
+            BPS as u128,
+            Rounding::Down,
+        )?)?;
+
+        self.protocol_reserves = self
+            .protocol_reserves
+            .checked_add(reserves)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        if self.total_deposits == 0 {
+            return Ok(());
+        }
+
+        let depositor_fee = fee - reserves;
+
+        self.supply_index = mul_div(
+            self.supply_index,
+            self.total_deposits as u128 + depositor_fee as u128,
+            self.total_deposits as u128,
+            Rounding::Down,
+        )?;
+
+        self.update_totals()
+    }
+
+    // A loss at least as large as every deposit writes them all off. The index then stays at 1
+    // rather than 0 so share conversions keep working while holders withdraw their worthless
+    // shares; it starts over once the last of them is gone.
+    pub fn socialize_loss(&mut self, loss: u64) -> Result<()> {
+        if self.total_deposits == 0 {
+            return Ok(());
+        }
+
+        let remaining = self.total_deposits.saturating_sub(loss);
+
+        self.supply_index = mul_div(
+            self.supply_index,
+            remaining as u128,
+            self.total_deposits as u128,
+            Rounding::Down,
+        )?
+        .max(1);
+
+        self.update_totals()
+    }
+
+    pub fn update_totals(&mut self) -> Result<()> {
+        if self.total_deposited_shares == 0 && self.supply_index < WAD {
+            self.supply_index = WAD;
+        }
+
+        self.total_deposits = self.deposit_shares_to_amount(self.total_deposited_shares)?;
+        self.total_borrowed = self.borrow_shares_to_amount(self.total_borrowed_shares)?;
+
+        Ok(())
+    }
 }
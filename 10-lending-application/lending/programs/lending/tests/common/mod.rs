@@ -0,0 +1,444 @@
+// Bank-backed runtime for the lending program. The program runs as a native builtin so the tests
+// don't need an SBF build; transactions still go through the real runtime, which enforces
+// signer, writable, ownership and rent rules and provides the instructions sysvar.
+
+#![allow(dead_code)]
+
+use std::collections::{HashMap, HashSet};
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{
+        entrypoint::ProgramResult, hash::Hash, instruction::Instruction, program::invoke,
+        program_error::ProgramError, program_pack::Pack,
+    },
+    AccountDeserialize, AccountSerialize,
+};
+use anchor_spl::{
+    associated_token::get_associated_token_address_with_program_id,
+    token::spl_token::{self, native_mint},
+    token_2022::spl_token_2022::{
+        self,
+        extension::{
+            permanent_delegate::PermanentDelegate,
+            transfer_fee::{TransferFeeAmount, TransferFeeConfig},
+            BaseStateWithExtensions, BaseStateWithExtensionsMut, ExtensionType,
+            StateWithExtensions, StateWithExtensionsMut,
+        },
+    },
+    token_2022_extensions::spl_pod::optional_keys::OptionalNonZeroPubkey,
+};
+use pyth_solana_receiver_sdk::price_update::{PriceFeedMessage, PriceUpdateV2, VerificationLevel};
+use solana_program_test::{
+    processor, tokio, ProgramTest, ProgramTestBanksClientExt, ProgramTestContext,
+};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    signature::{Keypair, Signature, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+pub struct Runtime {
+    executor: tokio::runtime::Runtime,
+    context: ProgramTestContext,
+    signers: HashMap<Pubkey, Keypair>,
+    sent: HashSet<(Hash, Signature)>,
+    return_data: Option<Vec<u8>>,
+}
+
+// Anchor's entrypoint ties the account slice to the accounts' own lifetime.
+fn entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(accounts.to_vec().into_boxed_slice());
+    lending::entry(program_id, accounts, data)
+}
+
+// A program that passes its instruction on to the lending program, so it runs as a CPI.
+pub const RELAY_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+
+fn relay(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let instruction = Instruction {
+        program_id: lending::ID,
+        accounts: accounts[1..]
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: *account.key,
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data: data.to_vec(),
+    };
+
+    invoke(&instruction, accounts)
+}
+
+// Token-2022 extensions a test mint can be created with.
+pub enum MintExtension {
+    TransferFee { basis_points: u16 },
+    PermanentDelegate(Pubkey),
+}
+
+impl Runtime {
+    pub fn new() -> Self {
+        let executor = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let mut program_test = ProgramTest::new("lending", lending::ID, processor!(entry));
+        program_test.prefer_bpf(false);
+        program_test.add_program("relay", RELAY_ID, processor!(relay));
+        let context = executor.block_on(program_test.start_with_context());
+
+        let runtime = Self {
+            executor,
+            context,
+            signers: HashMap::new(),
+            sent: HashSet::new(),
+            return_data: None,
+        };
+
+        let mut clock = runtime.clock();
+        clock.unix_timestamp = 1_700_000_000;
+        runtime.context.set_sysvar(&clock);
+
+        runtime
+    }
+
+    // A funded keypair that `process` signs with whenever it is marked as a signer.
+    pub fn new_signer(&mut self, lamports: u64) -> Pubkey {
+        let keypair = Keypair::new();
+        let key = keypair.pubkey();
+
+        self.airdrop(&key, lamports);
+        self.signers.insert(key, keypair);
+
+        key
+    }
+
+    pub fn set_account(&mut self, key: Pubkey, account: Account) {
+        self.context.set_account(&key, &AccountSharedData::from(account));
+    }
+
+    pub fn account(&self, key: &Pubkey) -> Option<Account> {
+        let mut banks_client = self.context.banks_client.clone();
+        self.executor.block_on(banks_client.get_account(*key)).unwrap()
+    }
+
+    pub fn get<T: AccountDeserialize>(&self, key: &Pubkey) -> T {
+        let account = self.account(key).expect("account not found");
+        T::try_deserialize(&mut account.data.as_slice()).expect("failed to deserialize account")
+    }
+
+    pub fn lamports(&self, key: &Pubkey) -> u64 {
+        self.account(key).map_or(0, |account| account.lamports)
+    }
+
+    pub fn airdrop(&mut self, key: &Pubkey, lamports: u64) {
+        let mut account = self.account(key).unwrap_or_default();
+        account.lamports += lamports;
+        self.set_account(*key, account);
+    }
+
+    pub fn clock(&self) -> Clock {
+        let mut banks_client = self.context.banks_client.clone();
+        self.executor.block_on(banks_client.get_sysvar()).unwrap()
+    }
+
+    pub fn warp(&mut self, seconds: i64) {
+        let mut clock = self.clock();
+        clock.unix_timestamp += seconds;
+        clock.slot += seconds as u64 * 2;
+        self.context.set_sysvar(&clock);
+    }
+
+    pub fn create_mint(&mut self, authority: &Pubkey, decimals: u8) -> Pubkey {
+        let key = Pubkey::new_unique();
+        let mint = spl_token::state::Mint {
+            mint_authority: Some(*authority).into(),
+            supply: 0,
+            decimals,
+            is_initialized: true,
+            freeze_authority: None.into(),
+        };
+
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(mint, &mut data).unwrap();
+        self.set_account(key, self.rent_exempt(data, spl_token::ID));
+
+        key
+    }
+
+    pub fn create_token_2022_mint(
+        &mut self,
+        authority: &Pubkey,
+        decimals: u8,
+        extensions: &[MintExtension],
+    ) -> Pubkey {
+        let key = Pubkey::new_unique();
+        let extension_types: Vec<ExtensionType> = extensions
+            .iter()
+            .map(|extension| match extension {
+                MintExtension::TransferFee { .. } => ExtensionType::TransferFeeConfig,
+                MintExtension::PermanentDelegate(_) => ExtensionType::PermanentDelegate,
+            })
+            .collect();
+
+        let len =
+            ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&extension_types)
+                .unwrap();
+        let mut data = vec![0; len];
+        let mut state =
+            StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data)
+                .unwrap();
+
+        for extension in extensions {
+            match *extension {
+                MintExtension::TransferFee { basis_points } => {
+                    let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+                    for fee in [&mut config.older_transfer_fee, &mut config.newer_transfer_fee] {
+                        fee.maximum_fee = u64::MAX.into();
+                        fee.transfer_fee_basis_points = basis_points.into();
+                    }
+                }
+                MintExtension::PermanentDelegate(delegate) => {
+                    state.init_extension::<PermanentDelegate>(true).unwrap().delegate =
+                        OptionalNonZeroPubkey::try_from(Some(delegate)).unwrap();
+                }
+            }
+        }
+
+        state.base = spl_token_2022::state::Mint {
+            mint_authority: Some(*authority).into(),
+            supply: 0,
+            decimals,
+            is_initialized: true,
+            freeze_authority: None.into(),
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+
+        self.set_account(key, self.rent_exempt(data, spl_token_2022::ID));
+
+        key
+    }
+
+    // Overwrites the supply of a classic SPL Token mint.
+    pub fn set_mint_supply(&mut self, mint: &Pubkey, supply: u64) {
+        let mut account = self.account(mint).expect("mint not found");
+        let mut state = spl_token::state::Mint::unpack(&account.data).unwrap();
+
+        state.supply = supply;
+        spl_token::state::Mint::pack(state, &mut account.data).unwrap();
+        self.set_account(*mint, account);
+    }
+
+    pub fn create_native_mint(&mut self) -> Pubkey {
+        let mint = spl_token::state::Mint {
+            mint_authority: None.into(),
+            supply: 0,
+            decimals: native_mint::DECIMALS,
+            is_initialized: true,
+            freeze_authority: None.into(),
+        };
+
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(mint, &mut data).unwrap();
+        self.set_account(native_mint::ID, self.rent_exempt(data, spl_token::ID));
+
+        native_mint::ID
+    }
+
+    // Native-mint accounts hold their balance as lamports on top of the rent reserve.
+    pub fn create_ata(&mut self, owner: &Pubkey, mint: &Pubkey, amount: u64) -> Pubkey {
+        let token_program = self.account(mint).expect("mint not found").owner;
+
+        if token_program == spl_token_2022::ID {
+            return self.create_token_2022_ata(owner, mint, amount);
+        }
+
+        let key = get_associated_token_address_with_program_id(owner, mint, &spl_token::ID);
+        let rent = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+        let is_native = *mint == native_mint::ID;
+        let account = spl_token::state::Account {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            is_native: is_native.then_some(rent).into(),
+            ..spl_token::state::Account::default()
+        };
+
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(account, &mut data).unwrap();
+
+        let mut account = self.rent_exempt(data, spl_token::ID);
+        if is_native {
+            account.lamports += amount;
+        }
+        self.set_account(key, account);
+
+        key
+    }
+
+    // Carries whatever account extensions the mint's extensions require.
+    fn create_token_2022_ata(&mut self, owner: &Pubkey, mint: &Pubkey, amount: u64) -> Pubkey {
+        let key = get_associated_token_address_with_program_id(owner, mint, &spl_token_2022::ID);
+        let mint_data = self.account(mint).unwrap().data;
+        let mint_state =
+            StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data).unwrap();
+        let extension_types = ExtensionType::get_required_init_account_extensions(
+            &mint_state.get_extension_types().unwrap(),
+        );
+
+        let len =
+            ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&extension_types)
+                .unwrap();
+        let mut data = vec![0; len];
+        let mut state =
+            StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack_uninitialized(&mut data)
+                .unwrap();
+
+        if extension_types.contains(&ExtensionType::TransferFeeAmount) {
+            state.init_extension::<TransferFeeAmount>(true).unwrap();
+        }
+
+        state.base = spl_token_2022::state::Account {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: spl_token_2022::state::AccountState::Initialized,
+            ..spl_token_2022::state::Account::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+
+        self.set_account(key, self.rent_exempt(data, spl_token_2022::ID));
+
+        key
+    }
+
+    pub fn token_balance(&self, key: &Pubkey) -> u64 {
+        let account = self.account(key).expect("token account not found");
+        assert!(account.owner == spl_token::ID || account.owner == spl_token_2022::ID);
+
+        spl_token::state::Account::unpack_from_slice(&account.data[..spl_token::state::Account::LEN])
+            .unwrap()
+            .amount
+    }
+
+    pub fn set_price(&mut self, key: Pubkey, feed_id: [u8; 32], price: i64, exponent: i32, conf: u64) {
+        let clock = self.clock();
+        let price_update = PriceUpdateV2 {
+            write_authority: Pubkey::default(),
+            verification_level: VerificationLevel::Full,
+            price_message: PriceFeedMessage {
+                feed_id,
+                price,
+                conf,
+                exponent,
+                publish_time: clock.unix_timestamp,
+                prev_publish_time: clock.unix_timestamp - 1,
+                ema_price: price,
+                ema_conf: conf,
+            },
+            posted_slot: clock.slot,
+        };
+
+        let mut data = Vec::with_capacity(PriceUpdateV2::LEN);
+        price_update.try_serialize(&mut data).unwrap();
+        self.set_account(key, self.rent_exempt(data, pyth_solana_receiver_sdk::ID));
+    }
+
+    // Return data of the last successful transaction.
+    pub fn return_data(&self) -> Option<Vec<u8>> {
+        self.return_data.clone()
+    }
+
+    fn rent_exempt(&self, data: Vec<u8>, owner: Pubkey) -> Account {
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    // Wraps `instruction` so that it reaches the lending program through the relay.
+    pub fn relayed(instruction: &Instruction) -> Instruction {
+        let mut accounts = vec![AccountMeta::new_readonly(lending::ID, false)];
+        accounts.extend(instruction.accounts.iter().cloned());
+
+        Instruction {
+            program_id: RELAY_ID,
+            accounts,
+            data: instruction.data.clone(),
+        }
+    }
+
+    pub fn process(&mut self, instruction: Instruction) -> std::result::Result<(), ProgramError> {
+        self.process_transaction(&[instruction])
+    }
+
+    // Sends the instructions as one transaction, signed by every registered signer they name.
+    pub fn process_transaction(
+        &mut self,
+        instructions: &[Instruction],
+    ) -> std::result::Result<(), ProgramError> {
+        let mut signers: Vec<&Keypair> = vec![&self.context.payer];
+
+        for meta in instructions.iter().flat_map(|instruction| &instruction.accounts) {
+            if meta.is_signer && signers.iter().all(|signer| signer.pubkey() != meta.pubkey) {
+                let keypair = self
+                    .signers
+                    .get(&meta.pubkey)
+                    .unwrap_or_else(|| panic!("no keypair for signer {}", meta.pubkey));
+                signers.push(keypair);
+            }
+        }
+
+        let mut transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &signers,
+            self.context.last_blockhash,
+        );
+
+        // The bank rejects an identical transaction under the same blockhash as already processed.
+        if !self.sent.insert((self.context.last_blockhash, transaction.signatures[0])) {
+            let mut banks_client = self.context.banks_client.clone();
+            let blockhash = self
+                .executor
+                .block_on(banks_client.get_new_latest_blockhash(&self.context.last_blockhash))
+                .unwrap();
+
+            transaction.sign(&signers, blockhash);
+            self.sent.insert((blockhash, transaction.signatures[0]));
+            self.context.last_blockhash = blockhash;
+        }
+
+        let result = self
+            .executor
+            .block_on(self.context.banks_client.process_transaction_with_metadata(transaction))
+            .unwrap();
+
+        match result.result {
+            Ok(()) => {
+                self.return_data = result
+                    .metadata
+                    .and_then(|metadata| metadata.return_data)
+                    .map(|return_data| return_data.data)
+                    .filter(|data| !data.is_empty());
+                Ok(())
+            }
+            Err(TransactionError::InstructionError(_, error)) => Err(ProgramError::try_from(error)
+                .unwrap_or_else(|error| panic!("unexpected instruction error {error:?}"))),
+            Err(error) => panic!("transaction failed: {error:?}"),
+        }
+    }
+}
+
+pub fn assert_error(result: std::result::Result<(), ProgramError>, error: lending::ErrorCode) {
+    assert_eq!(result, Err(ProgramError::Custom(error.into())));
+}
@@ -0,0 +1,2360 @@
+mod common;
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{
+        instruction::Instruction, program_error::ProgramError, system_program, sysvar,
+    },
+    InstructionData, ToAccountMetas,
+};
+use anchor_spl::{
+    associated_token::{get_associated_token_address_with_program_id, spl_associated_token_account},
+    token::spl_token::{self, native_mint},
+    token_2022::spl_token_2022,
+};
+use common::{assert_error, MintExtension, Runtime};
+use lending::{
+    constants::{MAX_BORROW_RATE, MAX_LATE_PENALTY_RATE, MAX_POSITIONS, PAUSE_BORROW, PAUSE_DEPOSIT, WAD},
+    Bank, EModeConfig, ErrorCode, FallbackPrice, FixedBorrow, FixedTermConfig, InterestRateModel,
+    IsolationConfig, ObligationHealth, OracleConfig, RiskConfig, User,
+};
+use pyth_solana_receiver_sdk::error::GetPriceError;
+
+const USDC: u64 = 1_000_000;
+const SOL: u64 = 1_000_000_000;
+const ONE_YEAR: i64 = 31_536_000;
+const ONE_DAY: i64 = 86_400;
+
+#[derive(Clone, Copy)]
+struct Reserve {
+    mint: Pubkey,
+    token_program: Pubkey,
+    bank: Pubkey,
+    price_update: Pubkey,
+    feed_id: [u8; 32],
+}
+
+impl Reserve {
+    fn new(mint: Pubkey, token_program: Pubkey, feed: u8) -> Self {
+        Self {
+            mint,
+            token_program,
+            bank: Pubkey::find_program_address(&[mint.as_ref()], &lending::ID).0,
+            price_update: Pubkey::new_unique(),
+            feed_id: [feed; 32],
+        }
+    }
+
+    fn pda(&self, prefix: &[u8]) -> Pubkey {
+        Pubkey::find_program_address(&[prefix, self.mint.as_ref()], &lending::ID).0
+    }
+
+    fn treasury(&self) -> Pubkey {
+        self.pda(b"treasury")
+    }
+
+    fn ata(&self, owner: &Pubkey) -> Pubkey {
+        get_associated_token_address_with_program_id(owner, &self.mint, &self.token_program)
+    }
+
+    // Native SOL moves as lamports, so lending instructions take no token account for it.
+    fn user_token_account(&self, owner: &Pubkey) -> Option<Pubkey> {
+        (self.mint != native_mint::ID).then(|| self.ata(owner))
+    }
+}
+
+struct Market {
+    runtime: Runtime,
+    admin: Pubkey,
+    usdc: Reserve,
+    sol: Reserve,
+    // Reserves individual tests list on top of USDC and SOL.
+    listed: Vec<Reserve>,
+}
+
+fn user_pda(owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[owner.as_ref()], &lending::ID).0
+}
+
+impl Market {
+    fn new() -> Self {
+        Self::build(false, None)
+    }
+
+    // A market whose SOL reserve uses the native mint.
+    fn native() -> Self {
+        Self::build(true, None)
+    }
+
+    // A market whose USDC is a Token-2022 mint charging `basis_points` on every transfer.
+    fn with_transfer_fee(basis_points: u16) -> Self {
+        Self::build(false, Some(basis_points))
+    }
+
+    fn build(native_sol: bool, usdc_transfer_fee: Option<u16>) -> Self {
+        let mut runtime = Runtime::new();
+        let admin = runtime.new_signer(100 * SOL);
+
+        let usdc = match usdc_transfer_fee {
+            Some(basis_points) => Reserve::new(
+                runtime.create_token_2022_mint(
+                    &admin,
+                    6,
+                    &[MintExtension::TransferFee { basis_points }],
+                ),
+                spl_token_2022::ID,
+                1,
+            ),
+            None => Reserve::new(runtime.create_mint(&admin, 6), spl_token::ID, 1),
+        };
+        let sol_mint = if native_sol {
+            runtime.create_native_mint()
+        } else {
+            runtime.create_mint(&admin, 9)
+        };
+        let sol = Reserve::new(sol_mint, spl_token::ID, 2);
+
+        let mut market = Self {
+            runtime,
+            admin,
+            usdc,
+            sol,
+            listed: Vec::new(),
+        };
+
+        market.set_price(false, 100_000_000);
+        market.set_price(true, 10_000_000_000);
+        market.init_bank(false).unwrap();
+        market.init_bank(true).unwrap();
+
+        market
+    }
+
+    // Lists another 6-decimal reserve for `mint`, priced at `price` with an exponent of -8.
+    fn list(&mut self, mint: Pubkey, price: i64) -> std::result::Result<Reserve, ProgramError> {
+        let token_program = self.runtime.account(&mint).unwrap().owner;
+        let reserve = Reserve::new(mint, token_program, 3 + self.listed.len() as u8);
+
+        self.runtime
+            .set_price(reserve.price_update, reserve.feed_id, price, -8, price as u64 / 1_000);
+        self.init_reserve(reserve)?;
+        self.listed.push(reserve);
+
+        Ok(reserve)
+    }
+
+    fn reserves(&self) -> impl Iterator<Item = &Reserve> {
+        [&self.usdc, &self.sol].into_iter().chain(&self.listed)
+    }
+
+    fn reserve(&self, sol: bool) -> &Reserve {
+        if sol {
+            &self.sol
+        } else {
+            &self.usdc
+        }
+    }
+
+    // Prices use an exponent of -8 and a 0.1% confidence interval.
+    fn set_price(&mut self, sol: bool, price: i64) {
+        let reserve = self.reserve(sol);
+        let (key, feed_id) = (reserve.price_update, reserve.feed_id);
+        self.runtime.set_price(key, feed_id, price, -8, price as u64 / 1_000);
+    }
+
+    // The bank's fallback price account, when its oracle config names one.
+    fn fallback_price(&self, sol: bool) -> Option<Pubkey> {
+        self.fallback_price_of(&self.reserve(sol).bank)
+    }
+
+    fn fallback_price_of(&self, bank: &Pubkey) -> Option<Pubkey> {
+        let oracle = self.runtime.get::<Bank>(bank).oracle;
+        oracle.has_fallback().then_some(oracle.fallback_oracle)
+    }
+
+    // The temporary account native SOL payouts to `signer` pass through.
+    fn unwrap_account(&self, sol: bool, signer: &Pubkey) -> Option<Pubkey> {
+        (self.reserve(sol).mint == native_mint::ID).then(|| {
+            Pubkey::find_program_address(&[b"unwrap", signer.as_ref()], &lending::ID).0
+        })
+    }
+
+    fn refresh_prices(&mut self) {
+        for sol in [false, true] {
+            let reserve = self.reserve(sol);
+            let key = reserve.price_update;
+            let price = self.runtime.get::<PriceUpdateV2Ref>(&key).0;
+            self.set_price(sol, price);
+        }
+    }
+
+    fn init_bank(&mut self, sol: bool) -> std::result::Result<(), ProgramError> {
+        self.init_reserve(*self.reserve(sol))
+    }
+
+    fn init_reserve(&mut self, reserve: Reserve) -> std::result::Result<(), ProgramError> {
+        let accounts = lending::accounts::InitBank {
+            signer: self.admin,
+            mint: reserve.mint,
+            bank: reserve.bank,
+            bank_token_account: reserve.treasury(),
+            insurance_fund: reserve.pda(b"insurance"),
+            receipt_mint: reserve.pda(b"receipt"),
+            receipt_vault: reserve.pda(b"receipt_vault"),
+            token_program: reserve.token_program,
+            system_program: system_program::ID,
+        };
+        let data = lending::instruction::InitBank {
+            risk_config: RiskConfig {
+                max_ltv: 7_500,
+                liquidation_threshold: 8_000,
+                liquidation_bonus: 500,
+                liquidation_close_factor: 5_000,
+            },
+            interest_rate_model: InterestRateModel {
+                base_rate: 200,
+                slope1: 400,
+                optimal_utilization: 8_000,
+                slope2: 7_500,
+                reserve_factor: 1_000,
+            },
+            oracle_config: OracleConfig {
+                feed_id: reserve.feed_id,
+                max_age: 60,
+                max_confidence: 200,
+                fallback_oracle: Pubkey::default(),
+                max_deviation: 0,
+            },
+        };
+
+        self.process(accounts, data, vec![])
+    }
+
+    fn update_isolation_config(
+        &mut self,
+        bank: Pubkey,
+        isolation_config: IsolationConfig,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = lending::accounts::UpdateIsolationConfig {
+            authority: self.admin,
+            bank,
+        };
+
+        self.process(
+            accounts,
+            lending::instruction::UpdateIsolationConfig { isolation_config },
+            vec![],
+        )
+    }
+
+    fn update_emode_config(
+        &mut self,
+        sol: bool,
+        emode_config: EModeConfig,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = lending::accounts::UpdateEModeConfig {
+            authority: self.admin,
+            bank: self.reserve(sol).bank,
+        };
+
+        self.process(accounts, lending::instruction::UpdateEmodeConfig { emode_config }, vec![])
+    }
+
+    fn update_fixed_term_config(
+        &mut self,
+        sol: bool,
+        fixed_term_config: FixedTermConfig,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = lending::accounts::UpdateFixedTermConfig {
+            authority: self.admin,
+            bank: self.reserve(sol).bank,
+        };
+
+        self.process(
+            accounts,
+            lending::instruction::UpdateFixedTermConfig { fixed_term_config },
+            vec![],
+        )
+    }
+
+    fn new_user(&mut self, usdc: u64, sol: u64) -> Pubkey {
+        let owner = self.runtime.new_signer(10 * SOL);
+        self.runtime.create_ata(&owner, &self.usdc.mint, usdc);
+        self.runtime.create_ata(&owner, &self.sol.mint, sol);
+
+        let accounts = lending::accounts::InitUser {
+            signer: owner,
+            user_account: user_pda(&owner),
+            system_program: system_program::ID,
+        };
+        self.process(accounts, lending::instruction::InitUser {}, vec![])
+            .unwrap();
+
+        owner
+    }
+
+    fn user(&self, owner: &Pubkey) -> User {
+        self.runtime.get(&user_pda(owner))
+    }
+
+    fn bank(&self, sol: bool) -> Bank {
+        self.runtime.get(&self.reserve(sol).bank)
+    }
+
+    fn balance(&self, owner: &Pubkey, sol: bool) -> u64 {
+        self.runtime
+            .token_balance(&self.reserve(sol).ata(owner))
+    }
+
+    fn deposited_shares(&self, owner: &Pubkey, sol: bool) -> u64 {
+        self.user(owner)
+            .position(&self.reserve(sol).bank)
+            .map_or(0, |position| position.deposited_shares)
+    }
+
+    fn borrowed_shares(&self, owner: &Pubkey, sol: bool) -> u64 {
+        self.user(owner)
+            .position(&self.reserve(sol).bank)
+            .map_or(0, |position| position.borrowed_shares)
+    }
+
+    // The isolated bank an obligation's debt is counted against: the one holding its
+    // isolated collateral, or the one still recording its debt.
+    fn isolated_bank(&self, owner: &Pubkey) -> Option<Pubkey> {
+        let user = self.user(owner);
+
+        self.reserves()
+            .map(|reserve| reserve.bank)
+            .find(|bank| {
+                user.position(bank).is_ok_and(|position| {
+                    position.isolated_debt > 0
+                        || (position.deposited_shares > 0
+                            && self.runtime.get::<Bank>(bank).isolation.isolated)
+                })
+            })
+    }
+
+    // [bank, price_update, fallback?] for every open position, as expected by health checks.
+    fn health_accounts(&self, owner: &Pubkey) -> Vec<AccountMeta> {
+        self.health_accounts_with(owner, None)
+    }
+
+    // Like `health_accounts`, plus the bank an instruction is about to touch.
+    fn health_accounts_with(&self, owner: &Pubkey, touched: Option<Pubkey>) -> Vec<AccountMeta> {
+        let user = self.user(owner);
+
+        self.reserves()
+            .filter(|reserve| {
+                touched == Some(reserve.bank)
+                    || user.positions
+                        .iter()
+                        .any(|position| !position.is_empty() && position.bank == reserve.bank)
+            })
+            .flat_map(|reserve| {
+                [
+                    Some(AccountMeta::new_readonly(reserve.bank, false)),
+                    Some(AccountMeta::new_readonly(reserve.price_update, false)),
+                    self.fallback_price_of(&reserve.bank)
+                        .map(|fallback| AccountMeta::new_readonly(fallback, false)),
+                ]
+            })
+            .flatten()
+            .collect()
+    }
+
+    fn deposit(&mut self, owner: Pubkey, sol: bool, amount: u64) -> std::result::Result<(), ProgramError> {
+        self.deposit_to(owner, *self.reserve(sol), amount)
+    }
+
+    fn deposit_to(
+        &mut self,
+        owner: Pubkey,
+        reserve: Reserve,
+        amount: u64,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = lending::accounts::Deposit {
+            signer: owner,
+            mint: reserve.mint,
+            bank: reserve.bank,
+            bank_token_account: reserve.treasury(),
+            user_account: user_pda(&owner),
+            receipt_mint: reserve.pda(b"receipt"),
+            receipt_vault: reserve.pda(b"receipt_vault"),
+            user_token_account: reserve.user_token_account(&owner),
+            token_program: reserve.token_program,
+            associated_token_program: spl_associated_token_account::ID,
+            system_program: system_program::ID,
+        };
+
+        let remaining_accounts = self.health_accounts_with(&owner, Some(reserve.bank));
+
+        self.process(accounts, lending::instruction::Deposit { amount }, remaining_accounts)
+    }
+
+    fn withdraw(&mut self, owner: Pubkey, sol: bool, amount: u64) -> std::result::Result<(), ProgramError> {
+        let reserve = self.reserve(sol);
+        let accounts = lending::accounts::Withdraw {
+            signer: owner,
+            mint: reserve.mint,
+            bank: reserve.bank,
+            bank_token_account: reserve.treasury(),
+            user_account: user_pda(&owner),
+            receipt_mint: reserve.pda(b"receipt"),
+            receipt_vault: reserve.pda(b"receipt_vault"),
+            user_token_account: reserve.user_token_account(&owner),
+            unwrap_account: self.unwrap_account(sol, &owner),
+            price_update: reserve.price_update,
+            fallback_price: self.fallback_price(sol),
+            token_program: reserve.token_program,
+            associated_token_program: spl_associated_token_account::ID,
+            system_program: system_program::ID,
+        };
+        let remaining_accounts = self.health_accounts(&owner);
+
+        self.process(accounts, lending::instruction::Withdraw { amount }, remaining_accounts)
+    }
+
+    fn borrow(&mut self, owner: Pubkey, sol: bool, amount: u64) -> std::result::Result<(), ProgramError> {
+        self.borrow_as(owner, owner, sol, amount)
+    }
+
+    fn borrow_allowance(&self, owner: &Pubkey, sol: bool, delegate: &Pubkey) -> Pubkey {
+        let bank = self.reserve(sol).bank;
+
+        Pubkey::find_program_address(
+            &[b"borrow_allowance", owner.as_ref(), bank.as_ref(), delegate.as_ref()],
+            &lending::ID,
+        )
+        .0
+    }
+
+    fn approve_borrow_delegate(&mut self, owner: Pubkey, sol: bool, delegate: Pubkey, amount: u64) {
+        let accounts = lending::accounts::ApproveBorrowDelegate {
+            owner,
+            user_account: user_pda(&owner),
+            bank: self.reserve(sol).bank,
+            delegate,
+            borrow_allowance: self.borrow_allowance(&owner, sol, &delegate),
+            system_program: system_program::ID,
+        };
+
+        self.process(accounts, lending::instruction::ApproveBorrowDelegate { amount }, vec![])
+            .unwrap();
+    }
+
+    fn revoke_borrow_delegate(&mut self, owner: Pubkey, sol: bool, delegate: Pubkey) {
+        let accounts = lending::accounts::RevokeBorrowDelegate {
+            owner,
+            bank: self.reserve(sol).bank,
+            delegate,
+            borrow_allowance: self.borrow_allowance(&owner, sol, &delegate),
+        };
+
+        self.process(accounts, lending::instruction::RevokeBorrowDelegate {}, vec![])
+            .unwrap();
+    }
+
+    // Accounts for `signer` borrowing against `owner`'s collateral.
+    fn borrow_accounts(&self, signer: Pubkey, owner: Pubkey, sol: bool) -> lending::accounts::Borrow {
+        let reserve = self.reserve(sol);
+
+        lending::accounts::Borrow {
+            signer,
+            mint: reserve.mint,
+            bank: reserve.bank,
+            bank_token_account: reserve.treasury(),
+            user_account: user_pda(&owner),
+            borrow_allowance: (signer != owner).then(|| self.borrow_allowance(&owner, sol, &signer)),
+            user_token_account: reserve.user_token_account(&signer),
+            unwrap_account: self.unwrap_account(sol, &signer),
+            price_update: reserve.price_update,
+            fallback_price: self.fallback_price(sol),
+            isolated_bank: self.isolated_bank(&owner),
+            token_program: reserve.token_program,
+            associated_token_program: spl_associated_token_account::ID,
+            system_program: system_program::ID,
+        }
+    }
+
+    // Borrows against `owner`'s collateral with `signer` receiving the funds.
+    fn borrow_as(
+        &mut self,
+        signer: Pubkey,
+        owner: Pubkey,
+        sol: bool,
+        amount: u64,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = self.borrow_accounts(signer, owner, sol);
+        let remaining_accounts = self.health_accounts(&owner);
+
+        self.process(accounts, lending::instruction::Borrow { amount }, remaining_accounts)
+    }
+
+    fn fixed_borrow(&self, owner: &Pubkey, sol: bool) -> FixedBorrow {
+        self.user(owner)
+            .position(&self.reserve(sol).bank)
+            .map_or(FixedBorrow::default(), |position| position.fixed_borrow)
+    }
+
+    fn borrow_fixed(
+        &mut self,
+        owner: Pubkey,
+        sol: bool,
+        amount: u64,
+        term: i64,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = self.borrow_accounts(owner, owner, sol);
+        let remaining_accounts = self.health_accounts(&owner);
+
+        self.process(
+            accounts,
+            lending::instruction::BorrowFixed { amount, term },
+            remaining_accounts,
+        )
+    }
+
+    fn repay_accounts(&self, owner: Pubkey, sol: bool) -> lending::accounts::Repay {
+        let reserve = self.reserve(sol);
+
+        lending::accounts::Repay {
+            signer: owner,
+            mint: reserve.mint,
+            bank: reserve.bank,
+            bank_token_account: reserve.treasury(),
+            user_account: user_pda(&owner),
+            user_token_account: reserve.user_token_account(&owner),
+            isolated_bank: self.isolated_bank(&owner),
+            token_program: reserve.token_program,
+            associated_token_program: spl_associated_token_account::ID,
+            system_program: system_program::ID,
+        }
+    }
+
+    fn repay_fixed(&mut self, owner: Pubkey, sol: bool) -> std::result::Result<(), ProgramError> {
+        let accounts = self.repay_accounts(owner, sol);
+        let remaining_accounts = self.health_accounts(&owner);
+
+        self.process(accounts, lending::instruction::RepayFixed {}, remaining_accounts)
+    }
+
+    fn repay(&mut self, owner: Pubkey, sol: bool, amount: u64) -> std::result::Result<(), ProgramError> {
+        let accounts = self.repay_accounts(owner, sol);
+        let remaining_accounts = self.health_accounts(&owner);
+
+        self.process(accounts, lending::instruction::Repay { amount }, remaining_accounts)
+    }
+
+    // Accounts for liquidating SOL collateral against USDC debt.
+    fn liquidate_accounts(&self, liquidator: Pubkey, borrower: Pubkey) -> lending::accounts::Liquidate {
+        lending::accounts::Liquidate {
+            liquidator,
+            collateral_price_update: self.sol.price_update,
+            borrowed_price_update: self.usdc.price_update,
+            collateral_mint: self.sol.mint,
+            borrowed_mint: self.usdc.mint,
+            collateral_bank: self.sol.bank,
+            borrowed_bank: self.usdc.bank,
+            collateral_fallback_price: self.fallback_price(true),
+            borrowed_fallback_price: self.fallback_price(false),
+            collateral_bank_token_account: self.sol.treasury(),
+            borrowed_bank_token_account: self.usdc.treasury(),
+            collateral_receipt_mint: self.sol.pda(b"receipt"),
+            collateral_receipt_vault: self.sol.pda(b"receipt_vault"),
+            borrower,
+            borrower_account: user_pda(&borrower),
+            liquidator_collateral_token_account: self.sol.ata(&liquidator),
+            liquidator_borrowed_token_account: self.usdc.ata(&liquidator),
+            isolated_bank: None,
+            token_program: spl_token::ID,
+            associated_token_program: spl_associated_token_account::ID,
+            system_program: system_program::ID,
+        }
+    }
+
+    // Liquidates SOL collateral against USDC debt.
+    fn liquidate(
+        &mut self,
+        liquidator: Pubkey,
+        borrower: Pubkey,
+        repay_amount: u64,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = self.liquidate_accounts(liquidator, borrower);
+        let remaining_accounts = self.health_accounts(&borrower);
+
+        self.process(
+            accounts,
+            lending::instruction::Liquidate { repay_amount },
+            remaining_accounts,
+        )
+    }
+
+    fn get_max_borrow(&mut self, owner: Pubkey, sol: bool) -> u64 {
+        let reserve = self.reserve(sol);
+        let accounts = lending::accounts::GetPositionLimit {
+            user_account: user_pda(&owner),
+            bank: reserve.bank,
+            price_update: reserve.price_update,
+            fallback_price: self.fallback_price(sol),
+        };
+        let remaining_accounts = self.health_accounts(&owner);
+
+        self.process(accounts, lending::instruction::GetMaxBorrow {}, remaining_accounts)
+            .unwrap();
+
+        u64::from_le_bytes(self.runtime.return_data().unwrap().try_into().unwrap())
+    }
+
+    fn get_max_withdraw(&mut self, owner: Pubkey, sol: bool) -> u64 {
+        let reserve = self.reserve(sol);
+        let accounts = lending::accounts::GetPositionLimit {
+            user_account: user_pda(&owner),
+            bank: reserve.bank,
+            price_update: reserve.price_update,
+            fallback_price: self.fallback_price(sol),
+        };
+        let remaining_accounts = self.health_accounts(&owner);
+
+        self.process(accounts, lending::instruction::GetMaxWithdraw {}, remaining_accounts)
+            .unwrap();
+
+        u64::from_le_bytes(self.runtime.return_data().unwrap().try_into().unwrap())
+    }
+
+    fn get_obligation_health(&mut self, owner: Pubkey) -> ObligationHealth {
+        let accounts = lending::accounts::GetObligationHealth {
+            user_account: user_pda(&owner),
+        };
+        let remaining_accounts = self.health_accounts(&owner);
+
+        self.process(accounts, lending::instruction::GetObligationHealth {}, remaining_accounts)
+            .unwrap();
+
+        ObligationHealth::try_from_slice(&self.runtime.return_data().unwrap()).unwrap()
+    }
+
+    fn oracle_config(&self, sol: bool) -> OracleConfig {
+        self.bank(sol).oracle
+    }
+
+    fn update_oracle_config(
+        &mut self,
+        sol: bool,
+        oracle_config: OracleConfig,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = lending::accounts::UpdateOracleConfig {
+            authority: self.admin,
+            bank: self.reserve(sol).bank,
+        };
+
+        self.process(
+            accounts,
+            lending::instruction::UpdateOracleConfig { oracle_config },
+            vec![],
+        )
+    }
+
+    // Publishes a fallback price with an exponent of -8 and a 0.1% confidence interval.
+    fn update_fallback_price(&mut self, sol: bool, price: i64) {
+        let bank = self.reserve(sol).bank;
+        let accounts = lending::accounts::UpdateFallbackPrice {
+            authority: self.admin,
+            bank,
+            fallback_price: FallbackPrice::address(&bank),
+            system_program: system_program::ID,
+        };
+        let data = lending::instruction::UpdateFallbackPrice {
+            price,
+            exponent: -8,
+            conf: price as u64 / 1_000,
+        };
+
+        self.process(accounts, data, vec![]).unwrap();
+    }
+
+    fn update_flash_loan_fee(
+        &mut self,
+        sol: bool,
+        flash_loan_fee: u64,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = lending::accounts::UpdateFlashLoanFee {
+            authority: self.admin,
+            bank: self.reserve(sol).bank,
+        };
+
+        self.process(
+            accounts,
+            lending::instruction::UpdateFlashLoanFee { flash_loan_fee },
+            vec![],
+        )
+    }
+
+    fn update_interest_rate_model(
+        &mut self,
+        sol: bool,
+        interest_rate_model: InterestRateModel,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = lending::accounts::UpdateInterestRateModel {
+            authority: self.admin,
+            bank: self.reserve(sol).bank,
+        };
+
+        self.process(
+            accounts,
+            lending::instruction::UpdateInterestRateModel {
+                interest_rate_model,
+            },
+            vec![],
+        )
+    }
+
+    fn update_caps(
+        &mut self,
+        sol: bool,
+        deposit_cap: u64,
+        borrow_cap: u64,
+    ) -> std::result::Result<(), ProgramError> {
+        let accounts = lending::accounts::UpdateCaps {
+            authority: self.admin,
+            bank: self.reserve(sol).bank,
+        };
+
+        self.process(
+            accounts,
+            lending::instruction::UpdateCaps {
+                deposit_cap,
+                borrow_cap,
+            },
+            vec![],
+        )
+    }
+
+    fn fund_insurance(&mut self, sol: bool, amount: u64) -> std::result::Result<(), ProgramError> {
+        let reserve = self.reserve(sol);
+        let accounts = lending::accounts::FundInsurance {
+            authority: self.admin,
+            mint: reserve.mint,
+            bank: reserve.bank,
+            bank_token_account: reserve.treasury(),
+            insurance_fund: reserve.pda(b"insurance"),
+            token_program: reserve.token_program,
+        };
+
+        self.process(accounts, lending::instruction::FundInsurance { amount }, vec![])
+    }
+
+    fn settle_bad_debt(&mut self, borrower: Pubkey, sol: bool) -> std::result::Result<(), ProgramError> {
+        let reserve = self.reserve(sol);
+        let accounts = lending::accounts::SettleBadDebt {
+            signer: self.admin,
+            mint: reserve.mint,
+            bank: reserve.bank,
+            bank_token_account: reserve.treasury(),
+            insurance_fund: reserve.pda(b"insurance"),
+            borrower,
+            borrower_account: user_pda(&borrower),
+            token_program: reserve.token_program,
+        };
+
+        self.process(accounts, lending::instruction::SettleBadDebt {}, vec![])
+    }
+
+    fn close_bank(&mut self, sol: bool) -> std::result::Result<(), ProgramError> {
+        let reserve = *self.reserve(sol);
+        let accounts = lending::accounts::CloseBank {
+            authority: self.admin,
+            mint: reserve.mint,
+            bank: reserve.bank,
+            bank_token_account: reserve.treasury(),
+            insurance_fund: reserve.pda(b"insurance"),
+            receipt_vault: reserve.pda(b"receipt_vault"),
+            fallback_price: FallbackPrice::address(&reserve.bank),
+            authority_token_account: self.runtime.create_ata(&self.admin, &reserve.mint, 0),
+            token_program: reserve.token_program,
+        };
+
+        self.process(accounts, lending::instruction::CloseBank {}, vec![])
+    }
+
+    fn flash_borrow_ix(&self, signer: Pubkey, sol: bool, amount: u64) -> Instruction {
+        let reserve = self.reserve(sol);
+        let accounts = lending::accounts::FlashBorrow {
+            signer,
+            mint: reserve.mint,
+            bank: reserve.bank,
+            bank_token_account: reserve.treasury(),
+            user_token_account: reserve.ata(&signer),
+            instructions: sysvar::instructions::ID,
+            token_program: reserve.token_program,
+            associated_token_program: spl_associated_token_account::ID,
+            system_program: system_program::ID,
+        };
+
+        instruction(accounts, lending::instruction::FlashBorrow { amount }, vec![])
+    }
+
+    fn flash_repay_ix(&self, signer: Pubkey, sol: bool) -> Instruction {
+        let reserve = self.reserve(sol);
+        let accounts = lending::accounts::FlashRepay {
+            signer,
+            mint: reserve.mint,
+            bank: reserve.bank,
+            bank_token_account: reserve.treasury(),
+            user_token_account: reserve.ata(&signer),
+            token_program: reserve.token_program,
+        };
+
+        instruction(accounts, lending::instruction::FlashRepay {}, vec![])
+    }
+
+    fn process(
+        &mut self,
+        accounts: impl ToAccountMetas,
+        data: impl InstructionData,
+        remaining_accounts: Vec<AccountMeta>,
+    ) -> std::result::Result<(), ProgramError> {
+        self.runtime
+            .process(instruction(accounts, data, remaining_accounts))
+    }
+}
+
+fn instruction(
+    accounts: impl ToAccountMetas,
+    data: impl InstructionData,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let mut metas = accounts.to_account_metas(None);
+    metas.extend(remaining_accounts);
+
+    Instruction {
+        program_id: lending::ID,
+        accounts: metas,
+        data: data.data(),
+    }
+}
+
+// Reads the price back out of a PriceUpdateV2 account so it can be republished.
+struct PriceUpdateV2Ref(i64);
+
+impl AccountDeserialize for PriceUpdateV2Ref {
+    fn try_deserialize_unchecked(buf: &mut &[u8]) -> Result<Self> {
+        let update =
+            pyth_solana_receiver_sdk::price_update::PriceUpdateV2::try_deserialize_unchecked(buf)?;
+        Ok(Self(update.price_message.price))
+    }
+}
+
+#[test]
+fn init_bank_sets_indices_and_receipt_mint() {
+    let market = Market::new();
+    let bank = market.bank(false);
+
+    assert_eq!(bank.mint_address, market.usdc.mint);
+    assert_eq!(bank.authority, market.admin);
+    assert_eq!(bank.supply_index, WAD);
+    assert_eq!(bank.borrow_index, WAD);
+    assert_eq!(bank.receipt_mint, market.usdc.pda(b"receipt"));
+    assert_eq!(bank.mint_decimals, 6);
+}
+
+#[test]
+fn deposit_borrow_accrue_repay_withdraw() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(1_000 * USDC, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+
+    assert_eq!(market.deposited_shares(&lender, false), 10_000 * USDC);
+    assert_eq!(market.bank(false).total_deposits, 10_000 * USDC);
+    assert_eq!(market.runtime.token_balance(&market.usdc.pda(b"receipt_vault")), 10_000 * USDC);
+
+    market.borrow(borrower, false, 700 * USDC).unwrap();
+
+    assert_eq!(market.balance(&borrower, false), 1_700 * USDC);
+    assert_eq!(market.borrowed_shares(&borrower, false), 700 * USDC);
+    assert_eq!(market.bank(false).total_borrowed, 700 * USDC);
+
+    // Repaying needs no fresh prices.
+    market.runtime.warp(ONE_YEAR);
+
+    market.repay(borrower, false, 100 * USDC).unwrap();
+
+    let bank = market.bank(false);
+    assert!(bank.borrow_index > WAD);
+    assert!(bank.supply_index > WAD);
+    assert!(bank.protocol_reserves > 0);
+
+    let debt = bank
+        .borrow_shares_to_amount(market.borrowed_shares(&borrower, false))
+        .unwrap();
+    assert!(debt > 600 * USDC);
+
+    market.repay(borrower, false, debt).unwrap();
+    assert_eq!(market.borrowed_shares(&borrower, false), 0);
+    assert_eq!(market.bank(false).total_borrowed_shares, 0);
+
+    market.withdraw(borrower, true, 10 * SOL).unwrap();
+    assert_eq!(market.balance(&borrower, true), 10 * SOL);
+    assert!(market.user(&borrower).positions.iter().all(|position| position.is_empty()));
+
+    let bank = market.bank(false);
+    let lender_balance = bank
+        .deposit_shares_to_amount(market.deposited_shares(&lender, false))
+        .unwrap();
+    assert!(lender_balance > 10_000 * USDC);
+
+    market.withdraw(lender, false, lender_balance).unwrap();
+    assert_eq!(market.balance(&lender, false), lender_balance);
+    assert_eq!(market.bank(false).total_deposited_shares, 0);
+    assert_eq!(market.runtime.token_balance(&market.usdc.pda(b"receipt_vault")), 0);
+}
+
+#[test]
+fn borrowers_can_de_risk_while_prices_are_stale() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 10 * SOL);
+    let borrower = market.new_user(1_000 * USDC, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 5 * SOL).unwrap();
+    market.borrow(borrower, false, 300 * USDC).unwrap();
+
+    // Neither primary has a fallback, so nothing can be priced.
+    market.runtime.warp(61);
+    assert_eq!(
+        market.borrow(borrower, false, USDC),
+        Err(ProgramError::Custom(GetPriceError::PriceTooOld.into()))
+    );
+
+    market.repay(borrower, false, 100 * USDC).unwrap();
+    market.deposit(borrower, true, 5 * SOL).unwrap();
+
+    // Without the price accounts at all.
+    let accounts = market.repay_accounts(borrower, false);
+    market
+        .process(accounts, lending::instruction::Repay { amount: 100 * USDC }, vec![])
+        .unwrap();
+
+    assert_eq!(market.deposited_shares(&borrower, true), 10 * SOL);
+    assert!(market.borrowed_shares(&borrower, false) < 110 * USDC);
+}
+
+#[test]
+fn borrow_above_max_ltv_fails() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+
+    assert_eq!(market.get_max_borrow(borrower, false), 750 * USDC);
+
+    assert_error(
+        market.borrow(borrower, false, 751 * USDC),
+        ErrorCode::OverBorrowableAmount,
+    );
+    market.borrow(borrower, false, 750 * USDC).unwrap();
+
+    assert_eq!(market.get_max_borrow(borrower, false), 0);
+}
+
+#[test]
+fn views_match_what_withdraw_allows() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+
+    assert_eq!(market.get_max_withdraw(borrower, true), 10 * SOL);
+
+    market.borrow(borrower, false, 300 * USDC).unwrap();
+
+    let health = market.get_obligation_health(borrower);
+    assert_eq!(health.borrowable_value, 750 * WAD);
+    assert_eq!(health.liquidation_value, 800 * WAD);
+    assert_eq!(health.debt_value, 300 * WAD);
+    assert_eq!(health.health_factor, 800 * WAD / 300);
+
+    // $450 of headroom at a 75% max LTV frees $600 of SOL.
+    let max_withdraw = market.get_max_withdraw(borrower, true);
+    assert_eq!(max_withdraw, 6 * SOL);
+
+    assert_error(
+        market.withdraw(borrower, true, max_withdraw + 1),
+        ErrorCode::UnhealthyPosition,
+    );
+    market.withdraw(borrower, true, max_withdraw).unwrap();
+
+    let health = market.get_obligation_health(borrower);
+    assert_eq!(health.borrowable_value, health.debt_value);
+    assert_eq!(health.health_factor, 320 * WAD / 300);
+    assert_eq!(market.get_max_withdraw(borrower, true), 0);
+}
+
+#[test]
+fn max_withdraw_frees_collateral_that_backs_no_borrowing() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(1_000 * USDC, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, false, 1_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+    market.borrow(borrower, false, 300 * USDC).unwrap();
+
+    // USDC debt is not borrowable in isolation, so the SOL adds nothing to borrowing power.
+    market.update_isolation_config(market.sol.bank, ISOLATED).unwrap();
+
+    assert_eq!(market.get_obligation_health(borrower).borrowable_value, 750 * WAD);
+    assert_eq!(market.get_max_withdraw(borrower, true), 10 * SOL);
+
+    // While the obligation is unhealthy, none of it can go.
+    let risk_config = RiskConfig {
+        max_ltv: 2_500,
+        ..market.bank(false).risk_config()
+    };
+    market
+        .process(
+            lending::accounts::UpdateBankConfig {
+                authority: market.admin,
+                bank: market.usdc.bank,
+            },
+            lending::instruction::UpdateBankConfig { risk_config },
+            vec![],
+        )
+        .unwrap();
+
+    assert_eq!(market.get_max_withdraw(borrower, true), 0);
+    assert_error(
+        market.withdraw(borrower, true, 1),
+        ErrorCode::UnhealthyPosition,
+    );
+
+    market.repay(borrower, false, 100 * USDC).unwrap();
+
+    assert_eq!(market.get_max_withdraw(borrower, true), 10 * SOL);
+    market.withdraw(borrower, true, 10 * SOL).unwrap();
+    assert_eq!(market.get_obligation_health(borrower).borrowable_value, 250 * WAD);
+}
+
+#[test]
+fn withdraw_that_breaks_health_fails() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+    market.borrow(borrower, false, 500 * USDC).unwrap();
+
+    assert_error(
+        market.withdraw(borrower, true, 4 * SOL),
+        ErrorCode::UnhealthyPosition,
+    );
+    market.withdraw(borrower, true, 3 * SOL).unwrap();
+
+    assert_error(
+        market.withdraw(lender, false, 10_001 * USDC),
+        ErrorCode::InsufficientFunds,
+    );
+}
+
+#[test]
+fn repay_more_than_debt_fails() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(100 * USDC, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+    market.borrow(borrower, false, 50 * USDC).unwrap();
+
+    assert_error(market.repay(borrower, false, 51 * USDC), ErrorCode::OverRepay);
+}
+
+#[test]
+fn liquidation_after_price_drop() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+    let liquidator = market.new_user(1_000 * USDC, 0);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+    market.borrow(borrower, false, 750 * USDC).unwrap();
+
+    assert_error(
+        market.liquidate(liquidator, borrower, 100 * USDC),
+        ErrorCode::NotUnderCollateralized,
+    );
+
+    // $900 of collateral at an 80% threshold no longer covers $750 of debt.
+    market.set_price(true, 9_000_000_000);
+
+    market.liquidate(liquidator, borrower, 1_000 * USDC).unwrap();
+
+    // Repay is capped at the 50% close factor; the 5% bonus is paid in SOL at $90.
+    assert_eq!(market.balance(&liquidator, false), 625 * USDC);
+    assert_eq!(market.balance(&liquidator, true), 4_375_000_000);
+    assert_eq!(market.borrowed_shares(&borrower, false), 375 * USDC);
+    assert_eq!(market.deposited_shares(&borrower, true), 5_625_000_000);
+
+    let sol_bank = market.bank(true);
+    assert_eq!(sol_bank.total_deposited_shares, 5_625_000_000);
+    assert_eq!(
+        market.runtime.token_balance(&market.sol.pda(b"receipt_vault")),
+        5_625_000_000
+    );
+}
+
+#[test]
+fn stale_or_uncertain_prices_are_rejected() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+
+    market.runtime.warp(61);
+    assert_eq!(
+        market.borrow(borrower, false, 100 * USDC),
+        Err(ProgramError::Custom(GetPriceError::PriceTooOld.into()))
+    );
+
+    market.refresh_prices();
+    let (key, feed_id) = (market.sol.price_update, market.sol.feed_id);
+    market.runtime.set_price(key, feed_id, 10_000_000_000, -8, 300_000_000);
+
+    assert_error(
+        market.borrow(borrower, false, 100 * USDC),
+        ErrorCode::PriceConfidenceTooWide,
+    );
+}
+
+#[test]
+fn paused_operations_are_rejected() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let guardian = market.runtime.new_signer(SOL);
+
+    assert_eq!(market.bank(false).pause_guardian, Pubkey::default());
+
+    let accounts = |authority: Pubkey, bank: Pubkey| lending::accounts::UpdatePauseGuardian { authority, bank };
+    let update_pause_guardian = |pause_guardian: Pubkey| lending::instruction::UpdatePauseGuardian { pause_guardian };
+
+    assert_error(
+        market.process(
+            accounts(market.admin, market.usdc.bank),
+            update_pause_guardian(market.admin),
+            vec![],
+        ),
+        ErrorCode::InvalidPauseGuardian,
+    );
+    market
+        .process(
+            accounts(market.admin, market.usdc.bank),
+            update_pause_guardian(guardian),
+            vec![],
+        )
+        .unwrap();
+
+    let pause = |signer: Pubkey, bank: Pubkey| lending::accounts::Pause { signer, bank };
+
+    assert_error(
+        market.process(
+            pause(lender, market.usdc.bank),
+            lending::instruction::Pause {
+                operations: PAUSE_DEPOSIT,
+            },
+            vec![],
+        ),
+        ErrorCode::InvalidPauseAuthority,
+    );
+
+    market
+        .process(
+            pause(guardian, market.usdc.bank),
+            lending::instruction::Pause {
+                operations: PAUSE_DEPOSIT | PAUSE_BORROW,
+            },
+            vec![],
+        )
+        .unwrap();
+
+    assert_eq!(market.bank(false).paused, PAUSE_DEPOSIT | PAUSE_BORROW);
+    assert_error(
+        market.deposit(lender, false, 1_000 * USDC),
+        ErrorCode::MarketPaused,
+    );
+
+    let set_paused = |authority: Pubkey, bank: Pubkey| lending::accounts::SetPaused { authority, bank };
+
+    assert_error(
+        market.process(
+            set_paused(market.admin, market.usdc.bank),
+            lending::instruction::SetPaused { paused: 1 << 7 },
+            vec![],
+        ),
+        ErrorCode::InvalidPauseFlags,
+    );
+    market
+        .process(
+            set_paused(market.admin, market.usdc.bank),
+            lending::instruction::SetPaused { paused: 0 },
+            vec![],
+        )
+        .unwrap();
+
+    market.deposit(lender, false, 1_000 * USDC).unwrap();
+}
+
+#[test]
+fn close_user_requires_empty_positions() {
+    let mut market = Market::new();
+    let lender = market.new_user(1_000 * USDC, 0);
+
+    market.deposit(lender, false, 1_000 * USDC).unwrap();
+
+    let close_user = |signer: Pubkey| lending::accounts::CloseUser {
+        signer,
+        user_account: user_pda(&signer),
+    };
+
+    assert_error(
+        market.process(close_user(lender), lending::instruction::CloseUser {}, vec![]),
+        ErrorCode::OpenPositions,
+    );
+
+    market.withdraw(lender, false, 1_000 * USDC).unwrap();
+    market
+        .process(close_user(lender), lending::instruction::CloseUser {}, vec![])
+        .unwrap();
+
+    assert!(market.runtime.account(&user_pda(&lender)).is_none());
+}
+
+#[test]
+fn closed_bank_can_be_relisted() {
+    let mut market = Market::new();
+    let lender = market.new_user(1_000 * USDC, 0);
+
+    market.deposit(lender, false, 1_000 * USDC).unwrap();
+    assert_error(market.close_bank(false), ErrorCode::BankNotEmpty);
+
+    market.withdraw(lender, false, 1_000 * USDC).unwrap();
+    market.update_fallback_price(false, 100_000_000);
+    market.close_bank(false).unwrap();
+
+    let bank = market.usdc.bank;
+    assert!(market.runtime.account(&bank).is_none());
+    assert!(market.runtime.account(&FallbackPrice::address(&bank)).is_none());
+
+    // The receipt mint outlives the bank and is picked up again, as long as no old shares
+    // are left to claim the new bank's deposits.
+    let receipt_mint = market.usdc.pda(b"receipt");
+    market.runtime.set_mint_supply(&receipt_mint, 1);
+    assert_error(market.init_bank(false), ErrorCode::ReceiptMintInUse);
+
+    market.runtime.set_mint_supply(&receipt_mint, 0);
+    market.init_bank(false).unwrap();
+    assert_eq!(market.bank(false).receipt_mint, market.reserve(false).pda(b"receipt"));
+
+    market.deposit(lender, false, 1_000 * USDC).unwrap();
+    assert_eq!(market.deposited_shares(&lender, false), 1_000 * USDC);
+}
+
+// Collateral that backs at most $500 of debt across every obligation.
+const ISOLATED: IsolationConfig = IsolationConfig {
+    isolated: true,
+    debt_ceiling: 500 * WAD,
+    borrowable_in_isolation: false,
+};
+
+const BORROWABLE_IN_ISOLATION: IsolationConfig = IsolationConfig {
+    isolated: false,
+    debt_ceiling: 0,
+    borrowable_in_isolation: true,
+};
+
+#[test]
+fn isolated_collateral_only_backs_stablecoin_debt_up_to_ceiling() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+
+    market.update_isolation_config(market.sol.bank, ISOLATED).unwrap();
+
+    assert_eq!(market.get_max_borrow(borrower, false), 0);
+    assert_error(
+        market.borrow(borrower, false, 100 * USDC),
+        ErrorCode::NotBorrowableInIsolation,
+    );
+
+    market
+        .update_isolation_config(market.usdc.bank, BORROWABLE_IN_ISOLATION)
+        .unwrap();
+
+    assert_eq!(market.get_max_borrow(borrower, false), 500 * USDC);
+    assert_error(
+        market.borrow(borrower, false, 501 * USDC),
+        ErrorCode::DebtCeilingExceeded,
+    );
+    market.borrow(borrower, false, 500 * USDC).unwrap();
+}
+
+#[test]
+fn isolated_debt_ceiling_is_shared_across_obligations() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let first = market.new_user(0, 10 * SOL);
+    let second = market.new_user(0, 10 * SOL);
+    let liquidator = market.new_user(1_000 * USDC, 0);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(first, true, 10 * SOL).unwrap();
+    market.deposit(second, true, 10 * SOL).unwrap();
+
+    market.update_isolation_config(market.sol.bank, ISOLATED).unwrap();
+    market
+        .update_isolation_config(market.usdc.bank, BORROWABLE_IN_ISOLATION)
+        .unwrap();
+
+    market.borrow(first, false, 300 * USDC).unwrap();
+    assert_eq!(market.bank(true).isolated_debt, 300 * WAD);
+
+    // Each obligation is well within 500 on its own; together they are not.
+    assert_eq!(market.get_max_borrow(second, false), 200 * USDC);
+    assert_error(
+        market.borrow(second, false, 201 * USDC),
+        ErrorCode::DebtCeilingExceeded,
+    );
+    market.borrow(second, false, 200 * USDC).unwrap();
+
+    market.repay(first, false, 100 * USDC).unwrap();
+    assert_eq!(market.bank(true).isolated_debt, 400 * WAD);
+
+    // $200 of SOL at an 80% threshold no longer covers $200 of debt.
+    market.set_price(true, 2_000_000_000);
+    market.liquidate(liquidator, first, 100 * USDC).unwrap();
+    assert_eq!(market.bank(true).isolated_debt, 300 * WAD);
+
+    market.repay(second, false, 200 * USDC).unwrap();
+    assert_eq!(market.bank(true).isolated_debt, 100 * WAD);
+    assert_eq!(market.user(&second).position(&market.sol.bank).unwrap().isolated_debt, 0);
+}
+
+#[test]
+fn emode_raises_ltv_when_all_positions_share_a_category() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+
+    let emode_config = EModeConfig {
+        category: 1,
+        max_ltv: 9_000,
+        liquidation_threshold: 9_300,
+    };
+
+    assert_error(
+        market.update_emode_config(
+            true,
+            EModeConfig {
+                max_ltv: 7_000,
+                ..emode_config
+            },
+        ),
+        ErrorCode::InvalidEModeConfig,
+    );
+
+    market.update_emode_config(true, emode_config).unwrap();
+    assert_eq!(market.get_max_borrow(borrower, false), 750 * USDC);
+
+    market.update_emode_config(false, emode_config).unwrap();
+    assert_eq!(market.get_max_borrow(borrower, false), 900 * USDC);
+
+    market.borrow(borrower, false, 900 * USDC).unwrap();
+    assert_error(
+        market.liquidate(borrower, borrower, 100 * USDC),
+        ErrorCode::NotUnderCollateralized,
+    );
+
+    // Leaving the category drops the obligation back to the base factors.
+    market
+        .update_emode_config(
+            false,
+            EModeConfig {
+                category: 2,
+                ..emode_config
+            },
+        )
+        .unwrap();
+    let liquidator = market.new_user(100 * USDC, 0);
+    market.liquidate(liquidator, borrower, 100 * USDC).unwrap();
+
+    assert_eq!(market.borrowed_shares(&borrower, false), 800 * USDC);
+}
+
+fn fixed_term_market() -> Market {
+    let mut market = Market::new();
+
+    market.update_fixed_term_config(
+        false,
+        FixedTermConfig {
+            max_term: 30 * ONE_DAY,
+            rate_premium: 100,
+            early_repay_penalty: 50,
+            late_penalty_rate: 2_000,
+        },
+    )
+    .unwrap();
+
+    market
+}
+
+// Terms an admin might switch to while loans are outstanding.
+const HARSHER_FIXED_TERMS: FixedTermConfig = FixedTermConfig {
+    max_term: 30 * ONE_DAY,
+    rate_premium: 100,
+    early_repay_penalty: 1_000,
+    late_penalty_rate: MAX_LATE_PENALTY_RATE,
+};
+
+#[test]
+fn fixed_borrow_locks_rate_and_charges_early_repay_penalty() {
+    let mut market = fixed_term_market();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(10 * USDC, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+
+    assert_error(
+        market.borrow_fixed(borrower, false, 500 * USDC, 31 * ONE_DAY),
+        ErrorCode::InvalidFixedTerm,
+    );
+    market.borrow_fixed(borrower, false, 500 * USDC, 30 * ONE_DAY).unwrap();
+
+    // 5% utilization prices the variable rate at 2.25%, plus the 1% premium.
+    let fixed_borrow = market.fixed_borrow(&borrower, false);
+    assert_eq!(fixed_borrow.principal, 500 * USDC);
+    assert_eq!(fixed_borrow.rate, 325);
+    assert_eq!(fixed_borrow.maturity - fixed_borrow.last_accrued, 30 * ONE_DAY);
+    assert_eq!(market.bank(false).total_fixed_borrowed, 500 * USDC);
+
+    assert_error(
+        market.borrow_fixed(borrower, false, 100 * USDC, ONE_DAY),
+        ErrorCode::FixedBorrowExists,
+    );
+
+    // The penalty terms are locked at origination too.
+    market.update_fixed_term_config(false, HARSHER_FIXED_TERMS).unwrap();
+    let fixed_borrow = market.fixed_borrow(&borrower, false);
+    assert_eq!(fixed_borrow.early_repay_penalty, 50);
+    assert_eq!(fixed_borrow.late_penalty_rate, 2_000);
+
+    market.runtime.warp(10 * ONE_DAY);
+    market.refresh_prices();
+
+    // Ten days of interest at 3.25% plus 0.5% of principal for repaying early.
+    let balance_before = market.balance(&borrower, false);
+    market.repay_fixed(borrower, false).unwrap();
+
+    assert_eq!(balance_before - market.balance(&borrower, false), 502_945_206);
+    assert_eq!(market.fixed_borrow(&borrower, false).principal, 0);
+    assert_eq!(market.bank(false).total_fixed_borrowed, 0);
+    assert!(market.bank(false).supply_index > WAD);
+
+    assert_error(market.repay_fixed(lender, false), ErrorCode::NoFixedBorrow);
+}
+
+#[test]
+fn fixed_borrow_keeps_its_collateral_locked() {
+    let mut market = fixed_term_market();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+    market.borrow_fixed(borrower, false, 500 * USDC, ONE_DAY).unwrap();
+
+    assert_error(
+        market.withdraw(borrower, true, 10 * SOL),
+        ErrorCode::UnhealthyPosition,
+    );
+    assert_eq!(market.deposited_shares(&borrower, true), 10 * SOL);
+}
+
+#[test]
+fn late_penalty_rate_is_bounded() {
+    let mut market = fixed_term_market();
+
+    assert_error(
+        market.update_fixed_term_config(
+            false,
+            FixedTermConfig {
+                late_penalty_rate: MAX_LATE_PENALTY_RATE + 1,
+                ..HARSHER_FIXED_TERMS
+            },
+        ),
+        ErrorCode::InvalidFixedTermConfig,
+    );
+}
+
+#[test]
+fn matured_fixed_borrow_can_be_liquidated() {
+    let mut market = fixed_term_market();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+    let liquidator = market.new_user(1_000 * USDC, 0);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+    market.borrow_fixed(borrower, false, 500 * USDC, ONE_DAY).unwrap();
+    market.update_fixed_term_config(false, HARSHER_FIXED_TERMS).unwrap();
+
+    assert_error(
+        market.liquidate(liquidator, borrower, 1_000 * USDC),
+        ErrorCode::NotUnderCollateralized,
+    );
+
+    market.runtime.warp(2 * ONE_DAY);
+    market.refresh_prices();
+
+    market.liquidate(liquidator, borrower, 1_000 * USDC).unwrap();
+
+    // A day at the locked 3.25% and a day at the 20% late penalty rate.
+    assert_eq!(market.balance(&liquidator, false), 1_000 * USDC - 500_318_494);
+    assert_eq!(market.fixed_borrow(&borrower, false).principal, 0);
+    assert_eq!(market.bank(false).total_fixed_borrowed, 0);
+    assert!(market.deposited_shares(&borrower, true) < 10 * SOL);
+}
+
+#[test]
+fn delegate_borrows_against_owner_collateral_within_allowance() {
+    let mut market = fixed_term_market();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let owner = market.new_user(0, 10 * SOL);
+    let delegate = market.new_user(0, 0);
+    let stranger = market.new_user(0, 0);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(owner, true, 10 * SOL).unwrap();
+    market.approve_borrow_delegate(owner, false, delegate, 300 * USDC);
+
+    market.borrow_as(delegate, owner, false, 200 * USDC).unwrap();
+
+    assert_eq!(market.balance(&delegate, false), 200 * USDC);
+    assert_eq!(market.balance(&owner, false), 0);
+    assert_eq!(market.borrowed_shares(&owner, false), 200 * USDC);
+    assert_eq!(market.borrowed_shares(&delegate, false), 0);
+
+    assert_error(
+        market.borrow_as(delegate, owner, false, 101 * USDC),
+        ErrorCode::BorrowAllowanceExceeded,
+    );
+    market.borrow_as(delegate, owner, false, 100 * USDC).unwrap();
+
+    // Fixed-term loans are the owner's to open, even within an allowance.
+    market.approve_borrow_delegate(owner, false, delegate, 300 * USDC);
+    let accounts = market.borrow_accounts(delegate, owner, false);
+    let remaining_accounts = market.health_accounts(&owner);
+
+    assert_error(
+        market.process(
+            accounts,
+            lending::instruction::BorrowFixed {
+                amount: 100 * USDC,
+                term: ONE_DAY,
+            },
+            remaining_accounts,
+        ),
+        ErrorCode::FixedBorrowOwnerOnly,
+    );
+    assert_eq!(market.fixed_borrow(&owner, false).principal, 0);
+
+    let mut accounts = market.borrow_accounts(stranger, owner, false);
+    accounts.borrow_allowance = None;
+    let remaining_accounts = market.health_accounts(&owner);
+
+    assert_error(
+        market.process(
+            accounts,
+            lending::instruction::Borrow { amount: USDC },
+            remaining_accounts,
+        ),
+        ErrorCode::InvalidBorrowDelegate,
+    );
+
+    // The owner's own borrows do not touch the delegate's allowance.
+    market.borrow(owner, false, 100 * USDC).unwrap();
+    assert_eq!(market.borrowed_shares(&owner, false), 400 * USDC);
+
+    market.approve_borrow_delegate(owner, false, delegate, 300 * USDC);
+    market.revoke_borrow_delegate(owner, false, delegate);
+
+    let borrow_allowance = market.borrow_allowance(&owner, false, &delegate);
+    assert!(market.runtime.account(&borrow_allowance).is_none());
+    assert!(market.borrow_as(delegate, owner, false, USDC).is_err());
+}
+
+#[test]
+fn flash_loan_is_repaid_with_fee_in_the_same_transaction() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let trader = market.new_user(10 * USDC, 0);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    assert_error(
+        market.update_flash_loan_fee(false, 10_001),
+        ErrorCode::InvalidFlashLoanFee,
+    );
+    market.update_flash_loan_fee(false, 9).unwrap();
+
+    let borrow = market.flash_borrow_ix(trader, false, 5_000 * USDC);
+    let repay = market.flash_repay_ix(trader, false);
+    market.runtime.process_transaction(&[borrow, repay]).unwrap();
+
+    // 9 bps on 5,000 USDC, split between suppliers and reserves.
+    assert_eq!(market.balance(&trader, false), 10 * USDC - 4_500_000);
+
+    let bank = market.bank(false);
+    assert_eq!(bank.flash_loan_amount, 0);
+    assert_eq!(bank.protocol_reserves, 450_000);
+    assert!(bank.supply_index > WAD);
+    assert_eq!(
+        market.runtime.token_balance(&market.usdc.treasury()),
+        10_000 * USDC + 4_500_000
+    );
+}
+
+#[test]
+fn flash_loan_requires_a_matching_repay() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 10 * SOL);
+    let trader = market.new_user(10 * USDC, SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(lender, true, 10 * SOL).unwrap();
+
+    let borrow = market.flash_borrow_ix(trader, false, 1_000 * USDC);
+    assert_error(
+        market.runtime.process(borrow.clone()),
+        ErrorCode::MissingFlashRepay,
+    );
+
+    // A repay against another bank does not close this loan.
+    let other_repay = market.flash_repay_ix(trader, true);
+    assert_error(
+        market.runtime.process_transaction(&[borrow.clone(), other_repay]),
+        ErrorCode::MissingFlashRepay,
+    );
+
+    let repay = market.flash_repay_ix(trader, false);
+    assert_error(
+        market.runtime.process(repay.clone()),
+        ErrorCode::NoFlashLoan,
+    );
+    assert_error(
+        market
+            .runtime
+            .process_transaction(&[borrow.clone(), borrow, repay]),
+        ErrorCode::FlashLoanInProgress,
+    );
+
+    // The repay scan only looks at top-level instructions, so a CPI borrow could go unchecked.
+    let borrow = market.flash_borrow_ix(trader, false, 1_000 * USDC);
+    let repay = market.flash_repay_ix(trader, false);
+    assert_error(
+        market
+            .runtime
+            .process_transaction(&[Runtime::relayed(&borrow), repay]),
+        ErrorCode::FlashLoanCpi,
+    );
+
+    assert_eq!(market.bank(false).flash_loan_amount, 0);
+    assert_eq!(market.balance(&trader, false), 10 * USDC);
+}
+
+#[test]
+fn fallback_price_only_replaces_a_stale_primary() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+
+    let oracle_config = OracleConfig {
+        fallback_oracle: Pubkey::new_unique(),
+        max_deviation: 100,
+        ..market.oracle_config(true)
+    };
+    assert_error(
+        market.update_oracle_config(true, oracle_config),
+        ErrorCode::InvalidOracleConfig,
+    );
+
+    market.update_fallback_price(true, 10_050_000_000);
+    market
+        .update_oracle_config(
+            true,
+            OracleConfig {
+                fallback_oracle: FallbackPrice::address(&market.sol.bank),
+                ..oracle_config
+            },
+        )
+        .unwrap();
+
+    // A stale primary is replaced by the fresh fallback.
+    market.runtime.warp(61);
+    market.update_fallback_price(true, 10_050_000_000);
+    let (key, feed_id) = (market.usdc.price_update, market.usdc.feed_id);
+    market.runtime.set_price(key, feed_id, 100_000_000, -8, 100_000);
+    market.borrow(borrower, false, 100 * USDC).unwrap();
+
+    // A fresh primary that fails for any other reason is not papered over.
+    market.refresh_prices();
+    let (key, feed_id) = (market.sol.price_update, market.sol.feed_id);
+    market.runtime.set_price(key, feed_id, 10_000_000_000, -8, 300_000_000);
+    assert_error(
+        market.borrow(borrower, false, 100 * USDC),
+        ErrorCode::PriceConfidenceTooWide,
+    );
+
+    // Both fresh but more than 1% apart.
+    market.refresh_prices();
+    market.update_fallback_price(true, 10_200_000_000);
+    assert_error(
+        market.borrow(borrower, false, 100 * USDC),
+        ErrorCode::PriceDeviationTooHigh,
+    );
+
+    // Both stale.
+    market.runtime.warp(61);
+    let (key, feed_id) = (market.usdc.price_update, market.usdc.feed_id);
+    market.runtime.set_price(key, feed_id, 100_000_000, -8, 100_000);
+    assert_error(
+        market.borrow(borrower, false, 100 * USDC),
+        ErrorCode::StalePrice,
+    );
+
+    // A configured fallback has to be passed, and has to be the configured one.
+    market.refresh_prices();
+    let mut remaining_accounts = market.health_accounts(&borrower);
+    remaining_accounts.last_mut().unwrap().pubkey = Pubkey::new_unique();
+    let accounts = market.borrow_accounts(borrower, borrower, false);
+    assert_error(
+        market.process(
+            accounts,
+            lending::instruction::Borrow { amount: 100 * USDC },
+            remaining_accounts,
+        ),
+        ErrorCode::InvalidFallbackOracle,
+    );
+
+    let liquidator = market.new_user(100 * USDC, 0);
+    let mut accounts = market.liquidate_accounts(liquidator, borrower);
+    accounts.collateral_fallback_price = None;
+    let remaining_accounts = market.health_accounts(&borrower);
+    assert_error(
+        market.process(
+            accounts,
+            lending::instruction::Liquidate {
+                repay_amount: 100 * USDC,
+            },
+            remaining_accounts,
+        ),
+        ErrorCode::InvalidFallbackOracle,
+    );
+}
+
+#[test]
+fn native_sol_payouts_leave_existing_wsol_wrapped() {
+    let mut market = Market::native();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(1_000 * USDC, SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+
+    // Deposits wrap lamports on the way in and leave the wSOL already held alone.
+    let lamports_before = market.runtime.lamports(&borrower);
+    market.deposit(borrower, true, 5 * SOL).unwrap();
+
+    assert_eq!(market.balance(&borrower, true), SOL);
+    assert_eq!(market.runtime.lamports(&borrower), lamports_before - 5 * SOL);
+
+    market.borrow(borrower, false, 100 * USDC).unwrap();
+    market.withdraw(borrower, true, 2 * SOL).unwrap();
+
+    assert_eq!(market.balance(&borrower, true), SOL);
+    assert_eq!(market.runtime.lamports(&borrower), lamports_before - 3 * SOL);
+    assert!(market
+        .runtime
+        .account(&market.unwrap_account(true, &borrower).unwrap())
+        .is_none());
+
+    // Borrowed SOL is paid out and repaid as lamports too, by a user who never holds wSOL.
+    let sol_borrower = market.runtime.new_signer(10 * SOL);
+    market.runtime.create_ata(&sol_borrower, &market.usdc.mint, 1_000 * USDC);
+    market
+        .process(
+            lending::accounts::InitUser {
+                signer: sol_borrower,
+                user_account: user_pda(&sol_borrower),
+                system_program: system_program::ID,
+            },
+            lending::instruction::InitUser {},
+            vec![],
+        )
+        .unwrap();
+    market.deposit(sol_borrower, false, 1_000 * USDC).unwrap();
+
+    let lamports_before = market.runtime.lamports(&sol_borrower);
+    market.borrow(sol_borrower, true, SOL).unwrap();
+
+    assert_eq!(market.runtime.lamports(&sol_borrower), lamports_before + SOL);
+
+    market.repay(sol_borrower, true, SOL / 2).unwrap();
+
+    assert_eq!(market.runtime.lamports(&sol_borrower), lamports_before + SOL / 2);
+    assert!(market.runtime.account(&market.sol.ata(&sol_borrower)).is_none());
+
+    let accounts = lending::accounts::Withdraw {
+        signer: borrower,
+        mint: market.sol.mint,
+        bank: market.sol.bank,
+        bank_token_account: market.sol.treasury(),
+        user_account: user_pda(&borrower),
+        receipt_mint: market.sol.pda(b"receipt"),
+        receipt_vault: market.sol.pda(b"receipt_vault"),
+        user_token_account: Some(market.sol.ata(&borrower)),
+        unwrap_account: None,
+        price_update: market.sol.price_update,
+        fallback_price: None,
+        token_program: spl_token::ID,
+        associated_token_program: spl_associated_token_account::ID,
+        system_program: system_program::ID,
+    };
+    let remaining_accounts = market.health_accounts(&borrower);
+
+    assert_error(
+        market.process(
+            accounts,
+            lending::instruction::Withdraw { amount: SOL },
+            remaining_accounts,
+        ),
+        ErrorCode::InvalidUnwrapAccount,
+    );
+}
+
+#[test]
+fn config_updates_are_validated() {
+    let mut market = Market::new();
+    let bank = market.usdc.bank;
+
+    let risk_config = RiskConfig {
+        max_ltv: 8_000,
+        liquidation_threshold: 8_000,
+        liquidation_bonus: 500,
+        liquidation_close_factor: 5_000,
+    };
+    assert_error(
+        market.process(
+            lending::accounts::UpdateBankConfig {
+                authority: market.admin,
+                bank,
+            },
+            lending::instruction::UpdateBankConfig { risk_config },
+            vec![],
+        ),
+        ErrorCode::InvalidRiskConfig,
+    );
+
+    let interest_rate_model = InterestRateModel {
+        base_rate: 200,
+        slope1: 400,
+        optimal_utilization: 10_000,
+        slope2: 7_500,
+        reserve_factor: 1_000,
+    };
+    assert_error(
+        market.update_interest_rate_model(false, interest_rate_model),
+        ErrorCode::InvalidInterestRateModel,
+    );
+
+    assert_error(
+        market.update_isolation_config(
+            bank,
+            IsolationConfig {
+                debt_ceiling: 0,
+                ..ISOLATED
+            },
+        ),
+        ErrorCode::InvalidIsolationConfig,
+    );
+    assert_error(
+        market.update_isolation_config(
+            bank,
+            IsolationConfig {
+                borrowable_in_isolation: true,
+                ..ISOLATED
+            },
+        ),
+        ErrorCode::InvalidIsolationConfig,
+    );
+}
+
+#[test]
+fn borrow_rates_are_capped() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 100 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 100 * SOL).unwrap();
+    market.borrow(borrower, false, 5_000 * USDC).unwrap();
+
+    let capped = InterestRateModel {
+        base_rate: 1_000,
+        slope1: 4_000,
+        optimal_utilization: 8_000,
+        slope2: MAX_BORROW_RATE - 5_000,
+        reserve_factor: 1_000,
+    };
+    assert_error(
+        market.update_interest_rate_model(
+            false,
+            InterestRateModel {
+                slope2: capped.slope2 + 1,
+                ..capped
+            },
+        ),
+        ErrorCode::InvalidInterestRateModel,
+    );
+    assert_error(
+        market.update_interest_rate_model(
+            false,
+            InterestRateModel {
+                base_rate: u64::MAX,
+                ..capped
+            },
+        ),
+        ErrorCode::InvalidInterestRateModel,
+    );
+    market.update_interest_rate_model(false, capped).unwrap();
+
+    // Years of accrual at the highest allowed rate leave the bank usable and reconfigurable.
+    market.runtime.warp(10 * ONE_YEAR);
+    market.update_interest_rate_model(false, capped).unwrap();
+
+    let bank = market.bank(false);
+    assert!(bank.borrow_index > WAD);
+    assert!(bank.total_borrowed > 5_000 * USDC);
+
+    market.repay(borrower, false, 100 * USDC).unwrap();
+}
+
+#[test]
+fn caps_limit_deposits_and_borrows() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    market.update_caps(false, 5_000 * USDC, 300 * USDC).unwrap();
+
+    assert_error(
+        market.deposit(lender, false, 5_001 * USDC),
+        ErrorCode::DepositCapExceeded,
+    );
+    market.deposit(lender, false, 5_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+
+    assert_error(
+        market.borrow(borrower, false, 301 * USDC),
+        ErrorCode::BorrowCapExceeded,
+    );
+    market.borrow(borrower, false, 300 * USDC).unwrap();
+}
+
+#[test]
+fn positions_are_opened_per_bank_up_to_the_limit() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 10 * SOL);
+
+    assert_error(market.deposit(lender, false, 0), ErrorCode::ZeroShares);
+    assert_error(market.withdraw(lender, true, SOL), ErrorCode::PositionNotFound);
+
+    market.deposit(lender, false, 1_000 * USDC).unwrap();
+    market.deposit(lender, true, SOL).unwrap();
+
+    for _ in 2..MAX_POSITIONS {
+        let mint = market.runtime.create_mint(&market.admin, 6);
+        let reserve = market.list(mint, 100_000_000).unwrap();
+        market.runtime.create_ata(&lender, &mint, 1_000 * USDC);
+        market.deposit_to(lender, reserve, 1_000 * USDC).unwrap();
+    }
+
+    let mint = market.runtime.create_mint(&market.admin, 6);
+    let reserve = market.list(mint, 100_000_000).unwrap();
+    market.runtime.create_ata(&lender, &mint, 1_000 * USDC);
+    assert_error(
+        market.deposit_to(lender, reserve, 1_000 * USDC),
+        ErrorCode::PositionLimitReached,
+    );
+
+    // Emptied positions free their slot.
+    market.withdraw(lender, true, SOL).unwrap();
+    market.deposit_to(lender, reserve, 1_000 * USDC).unwrap();
+}
+
+#[test]
+fn health_checks_need_every_position_priced() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+
+    // Without the SOL position the collateral would not count, and without its price
+    // it cannot be valued.
+    let accounts = market.borrow_accounts(borrower, borrower, false);
+    assert_error(
+        market.process(
+            accounts,
+            lending::instruction::Borrow { amount: 100 * USDC },
+            vec![],
+        ),
+        ErrorCode::MissingPositionAccounts,
+    );
+
+    let accounts = market.borrow_accounts(borrower, borrower, false);
+    assert_error(
+        market.process(
+            accounts,
+            lending::instruction::Borrow { amount: 100 * USDC },
+            vec![AccountMeta::new_readonly(market.sol.bank, false)],
+        ),
+        ErrorCode::MissingPositionAccounts,
+    );
+}
+
+#[test]
+fn unusable_prices_are_rejected() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+
+    let (key, feed_id) = (market.sol.price_update, market.sol.feed_id);
+
+    for (price, exponent) in [(0, -8), (-10_000_000_000, -8), (1, -30)] {
+        market.runtime.set_price(key, feed_id, price, exponent, 0);
+        assert_error(
+            market.borrow(borrower, false, 100 * USDC),
+            ErrorCode::InvalidPrice,
+        );
+    }
+
+    // Too large to scale to 18 decimals.
+    market.runtime.set_price(key, feed_id, i64::MAX, 2, 0);
+    assert_error(
+        market.borrow(borrower, false, 100 * USDC),
+        ErrorCode::MathOverflow,
+    );
+}
+
+#[test]
+fn liquidation_rejects_malformed_requests() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 10 * SOL);
+    let borrower = market.new_user(0, 10 * SOL);
+    let liquidator = market.new_user(1_000 * USDC, 0);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(lender, true, 10 * SOL).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+    market.borrow(borrower, false, 750 * USDC).unwrap();
+    market.set_price(true, 9_000_000_000);
+
+    assert_error(
+        market.liquidate(liquidator, borrower, 0),
+        ErrorCode::LiquidationTooSmall,
+    );
+
+    let mut accounts = market.liquidate_accounts(liquidator, borrower);
+    accounts.borrowed_mint = market.sol.mint;
+    accounts.borrowed_bank = market.sol.bank;
+    accounts.borrowed_price_update = market.sol.price_update;
+    accounts.borrowed_bank_token_account = market.sol.treasury();
+    accounts.liquidator_borrowed_token_account = market.sol.ata(&liquidator);
+    let remaining_accounts = market.health_accounts(&borrower);
+    assert_error(
+        market.process(
+            accounts,
+            lending::instruction::Liquidate { repay_amount: SOL },
+            remaining_accounts,
+        ),
+        ErrorCode::InvalidLiquidationBanks,
+    );
+
+    // Either bank passed again as the isolated bank would be written back twice.
+    for isolated_bank in [market.sol.bank, market.usdc.bank] {
+        let mut accounts = market.liquidate_accounts(liquidator, borrower);
+        accounts.isolated_bank = Some(isolated_bank);
+        let remaining_accounts = market.health_accounts(&borrower);
+        assert_error(
+            market.process(
+                accounts,
+                lending::instruction::Liquidate {
+                    repay_amount: 100 * USDC,
+                },
+                remaining_accounts,
+            ),
+            ErrorCode::InvalidIsolatedBank,
+        );
+    }
+}
+
+#[test]
+fn bad_debt_is_covered_by_insurance_before_suppliers() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+    let liquidator = market.new_user(1_000 * USDC, 0);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+    market.borrow(borrower, false, 750 * USDC).unwrap();
+
+    market.runtime.warp(ONE_YEAR);
+    market.refresh_prices();
+    market.repay(borrower, false, 0).unwrap();
+
+    let reserves = market.bank(false).protocol_reserves;
+    assert!(reserves > 0);
+    assert_error(
+        market.fund_insurance(false, reserves + 1),
+        ErrorCode::InsufficientReserves,
+    );
+    market.fund_insurance(false, reserves).unwrap();
+
+    // $100 of SOL left against more than $750 of debt.
+    market.set_price(true, 1_000_000_000);
+    assert_error(
+        market.settle_bad_debt(borrower, false),
+        ErrorCode::BorrowerHasCollateral,
+    );
+
+    market.liquidate(liquidator, borrower, 1_000 * USDC).unwrap();
+    assert_eq!(market.deposited_shares(&borrower, true), 0);
+
+    let supply_index = market.bank(false).supply_index;
+    market.settle_bad_debt(borrower, false).unwrap();
+
+    let bank = market.bank(false);
+    assert_eq!(market.borrowed_shares(&borrower, false), 0);
+    assert_eq!(bank.total_borrowed_shares, 0);
+    assert_eq!(market.runtime.token_balance(&market.usdc.pda(b"insurance")), 0);
+    assert!(bank.supply_index < supply_index);
+
+    assert_error(
+        market.settle_bad_debt(borrower, false),
+        ErrorCode::PositionNotFound,
+    );
+
+    // A zero borrow opens a position without any debt in it.
+    let stranger = market.new_user(0, 0);
+    market.borrow(stranger, false, 0).unwrap();
+    assert_error(
+        market.settle_bad_debt(stranger, false),
+        ErrorCode::NoBadDebt,
+    );
+}
+
+#[test]
+fn bad_debt_beyond_all_deposits_writes_them_off() {
+    let mut market = Market::new();
+    let lender = market.new_user(750 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+    let liquidator = market.new_user(1_000 * USDC, 0);
+    let depositor = market.new_user(1_000 * USDC, 0);
+
+    // Fully utilized, so debt outgrows deposits by the reserve factor's cut of the interest.
+    market.deposit(lender, false, 750 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+    market.borrow(borrower, false, 750 * USDC).unwrap();
+
+    market.runtime.warp(ONE_YEAR);
+    market.set_price(true, 100_000_000);
+    market.set_price(false, 100_000_000);
+    market.liquidate(liquidator, borrower, 1_000 * USDC).unwrap();
+    assert_eq!(market.deposited_shares(&borrower, true), 0);
+
+    market.settle_bad_debt(borrower, false).unwrap();
+
+    let bank = market.bank(false);
+    assert_eq!(bank.supply_index, 1);
+    assert_eq!(bank.total_deposits, 0);
+    assert_eq!(bank.total_deposited_shares, 750 * USDC);
+
+    // Withdrawing nothing clears the worthless shares, after which the bank takes deposits again.
+    market.withdraw(lender, false, 0).unwrap();
+    assert_eq!(market.deposited_shares(&lender, false), 0);
+    assert_eq!(market.bank(false).supply_index, WAD);
+
+    market.deposit(depositor, false, 1_000 * USDC).unwrap();
+    assert_eq!(market.deposited_shares(&depositor, false), 1_000 * USDC);
+    market.withdraw(depositor, false, 1_000 * USDC).unwrap();
+    assert_eq!(market.balance(&depositor, false), 1_000 * USDC);
+}
+
+#[test]
+fn isolated_debt_is_tracked_on_the_isolated_bank() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(100 * USDC, 10 * SOL);
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+    market.update_isolation_config(market.sol.bank, ISOLATED).unwrap();
+    market
+        .update_isolation_config(market.usdc.bank, BORROWABLE_IN_ISOLATION)
+        .unwrap();
+
+    let mut accounts = market.borrow_accounts(borrower, borrower, false);
+    accounts.isolated_bank = None;
+    let remaining_accounts = market.health_accounts(&borrower);
+    assert_error(
+        market.process(
+            accounts,
+            lending::instruction::Borrow { amount: 100 * USDC },
+            remaining_accounts,
+        ),
+        ErrorCode::InvalidIsolatedBank,
+    );
+
+    market.borrow(borrower, false, 100 * USDC).unwrap();
+
+    for isolated_bank in [None, Some(market.usdc.bank)] {
+        let mut accounts = market.repay_accounts(borrower, false);
+        accounts.isolated_bank = isolated_bank;
+        let remaining_accounts = market.health_accounts(&borrower);
+        assert_error(
+            market.process(
+                accounts,
+                lending::instruction::Repay { amount: 50 * USDC },
+                remaining_accounts,
+            ),
+            ErrorCode::InvalidIsolatedBank,
+        );
+    }
+
+    market.repay(borrower, false, 50 * USDC).unwrap();
+    assert_eq!(market.bank(true).isolated_debt, 50 * WAD);
+}
+
+#[test]
+fn debt_cannot_be_backed_by_two_isolated_collaterals() {
+    let mut market = Market::new();
+    let lender = market.new_user(10_000 * USDC, 0);
+    let borrower = market.new_user(0, 10 * SOL);
+
+    let mint = market.runtime.create_mint(&market.admin, 6);
+    let reserve = market.list(mint, 100_000_000).unwrap();
+    market.runtime.create_ata(&borrower, &mint, 1_000 * USDC);
+
+    market.update_isolation_config(market.sol.bank, ISOLATED).unwrap();
+    market.update_isolation_config(reserve.bank, ISOLATED).unwrap();
+    market
+        .update_isolation_config(market.usdc.bank, BORROWABLE_IN_ISOLATION)
+        .unwrap();
+
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    market.deposit(borrower, true, 10 * SOL).unwrap();
+    market.deposit_to(borrower, reserve, 1_000 * USDC).unwrap();
+
+    assert_error(
+        market.borrow(borrower, false, 100 * USDC),
+        ErrorCode::MultipleIsolatedCollateral,
+    );
+
+    market.withdraw(borrower, true, 10 * SOL).unwrap();
+    market.borrow(borrower, false, 100 * USDC).unwrap();
+    assert_eq!(market.runtime.get::<Bank>(&reserve.bank).isolated_debt, 100 * WAD);
+}
+
+#[test]
+fn transfer_fees_cannot_short_exact_repayments() {
+    let mut market = Market::with_transfer_fee(100);
+    let lender = market.new_user(10_000 * USDC, 0);
+    let trader = market.new_user(100 * USDC, 10 * SOL);
+
+    // Deposits are credited with what arrives after the 1% fee.
+    market.deposit(lender, false, 10_000 * USDC).unwrap();
+    assert_eq!(market.deposited_shares(&lender, false), 9_900 * USDC);
+
+    let borrow = market.flash_borrow_ix(trader, false, 1_000 * USDC);
+    let repay = market.flash_repay_ix(trader, false);
+    assert_error(
+        market.runtime.process_transaction(&[borrow, repay]),
+        ErrorCode::FlashLoanNotRepaid,
+    );
+
+    market.deposit(trader, true, 10 * SOL).unwrap();
+    assert_error(
+        market.borrow_fixed(trader, false, 100 * USDC, ONE_DAY),
+        ErrorCode::FixedTermDisabled,
+    );
+
+    market
+        .update_fixed_term_config(
+            false,
+            FixedTermConfig {
+                max_term: 30 * ONE_DAY,
+                rate_premium: 100,
+                early_repay_penalty: 50,
+                late_penalty_rate: 2_000,
+            },
+        )
+        .unwrap();
+    assert_error(
+        market.borrow_fixed(trader, false, 100 * USDC, ONE_DAY),
+        ErrorCode::TransferFeeNotSupported,
+    );
+}
+
+#[test]
+fn mints_that_can_move_bank_funds_are_not_listed() {
+    let mut market = Market::new();
+
+    let mint = market.runtime.create_token_2022_mint(
+        &market.admin,
+        6,
+        &[MintExtension::PermanentDelegate(Pubkey::new_unique())],
+    );
+    assert_error(
+        market.list(mint, 100_000_000).map(|_| ()),
+        ErrorCode::UnsupportedMintExtension,
+    );
+
+    let mint = market.runtime.create_token_2022_mint(
+        &market.admin,
+        6,
+        &[MintExtension::TransferFee { basis_points: 100 }],
+    );
+    market.list(mint, 100_000_000).unwrap();
+}